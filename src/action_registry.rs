@@ -2,15 +2,89 @@ use std::result::Result;
 
 use crate::error::Error;
 use crate::query::*;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use core::fmt::Display;
 use std::ops::Fn;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
 
-use crate::parse::parse_query_simple;
+use crate::parse::{parse, parse_query_simple};
+use crate::formats::{media_type_from_extension, Metadata, ValueSerializer};
+use crate::value::Value;
+
+/// Context of an evaluation, accessible to the context-aware actions.
+#[derive(Debug, Clone, Default)]
+pub struct EvalContext{
+    /// Current working directory; relative resource paths are resolved against it
+    pub cwd:Option<PathBuf>,
+    pub variables:BTreeMap<String, Value>,
+    /// Metadata hint describing the input value (e.g. media type of bytes), if provided by the caller
+    pub input_metadata:Option<Metadata>,
+}
+
+impl EvalContext{
+    pub fn new()->Self{
+        EvalContext::default()
+    }
+}
 
 pub trait CallableAction<T>{
     fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>;
+    /// Call action with an evaluation context.
+    /// The context is mutable, so that actions with side effects can record them (e.g. in `context.variables`);
+    /// ordinary (pure) actions ignore the context.
+    fn call_action_with_context(&self, input:T, arguments:&Vec<ActionParameter>, _context:&mut EvalContext) -> Result<T, Error>{
+        self.call_action(input, arguments)
+    }
+    /// True for loaders - actions ignoring the input and producing a value from a resource (see Loader).
+    fn is_loader(&self)->bool{
+        false
+    }
+    /// Number of parameters the action requires, if it is fixed; used to validate queries in advance (see compile).
+    fn expected_param_count(&self)->Option<usize>{
+        None
+    }
+    /// Call action producing its result (a list) element by element, see `HashMapActionRegistry::eval_stream`.
+    /// Only actions supporting chunked output (e.g. ChunkedAction) implement it; by default it fails.
+    fn call_action_stream<'a>(&'a self, _input:T, _arguments:&Vec<ActionParameter>, _context:&mut EvalContext) -> Result<ValueStream<'a,T>, Error>{
+        Err(Error::General{message:"Action does not support chunked output".to_owned()})
+    }
+}
+
+/// Elements of a list produced incrementally by an action supporting chunked output.
+pub type ValueStream<'a,T> = Box<dyn Iterator<Item=Result<T, Error>> + 'a>;
+
+/// Closure producing the elements of a list one by one.
+pub type StreamClosure<T> = Box<dyn Fn(T, &Vec<ActionParameter>) -> Result<ValueStream<'static,T>, Error>>;
+
+/// List-producing action supporting chunked output: the elements can be consumed as they are produced
+/// (see `HashMapActionRegistry::eval_stream`). When called as an ordinary action, the elements are collected into a list.
+pub struct ChunkedAction<T>(pub StreamClosure<T>);
+
+impl<T:From<Vec<T>>> CallableAction<T> for ChunkedAction<T>{
+    fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        Ok(T::from(self.0(input, arguments)?.collect::<Result<Vec<T>,Error>>()?))
+    }
+    fn call_action_stream<'a>(&'a self, input:T, arguments:&Vec<ActionParameter>, _context:&mut EvalContext) -> Result<ValueStream<'a,T>, Error>{
+        self.0(input, arguments)
+    }
+}
+
+/// Closure of a context-aware action.
+pub type ContextClosure<T> = Box<dyn Fn(T, &Vec<ActionParameter>, &mut EvalContext) -> Result<T, Error>>;
+
+/// Context-aware action, which may read and modify the context. When called without a context, it gets an empty context.
+pub struct ContextAction<T>(pub ContextClosure<T>);
+
+impl<T> CallableAction<T> for ContextAction<T>{
+    fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        self.0(input, arguments, &mut EvalContext::new())
+    }
+    fn call_action_with_context(&self, input:T, arguments:&Vec<ActionParameter>, context:&mut EvalContext) -> Result<T, Error>{
+        self.0(input, arguments, context)
+    }
 }
 /*
 impl<T,In,Out> CallableAction<T> for Fn(In)->Out
@@ -51,8 +125,59 @@ where
     }
 }
 
+/// Minimal requirements on a value type used with the registry and the function wrappers.
+///
+/// Any type implementing ValueSerializer and Clone is ValueLike (there is a blanket implementation).
+/// Beyond that, the wrappers only need the conversions for the types they use:
+/// for `Function1<In,Out>` the value type must implement `TryInto<In>` (with an error implementing Display)
+/// and `Out` must implement `Into<T>`; parameters are converted with `TryParameterFrom`.
+/// Evaluating queries additionally requires `Default` (the initial value for links and loaders)
+/// and `TryInto<String>` (to pass links and named results as parameters).
+pub trait ValueLike: ValueSerializer + Clone{}
+
+impl<T:ValueSerializer + Clone> ValueLike for T{}
+
+/// Adapter registering an action working with another value type `U` in a registry of `T`,
+/// e.g. to bridge two pipelines with different value types.
+/// Both types must be ValueLike and support the format used for the conversion:
+/// the input is serialized by `T::as_bytes` and deserialized by `U::from_bytes`, the result is converted back the same way.
+/// Parameters and the evaluation context are passed to the action unchanged.
+pub struct ConvertAction<T,U>{
+    action:Box<dyn CallableAction<U>>,
+    format:String,
+    marker:std::marker::PhantomData<T>
+}
+
+impl<T:ValueLike,U:ValueLike> ConvertAction<T,U>{
+    /// Wrap the action; the values are converted via the format (e.g. "json" or "txt").
+    pub fn new(action:Box<dyn CallableAction<U>>, format:&str)->Self{
+        ConvertAction{action, format:format.to_owned(), marker:std::marker::PhantomData}
+    }
+}
+
+impl<T:ValueLike,U:ValueLike> CallableAction<T> for ConvertAction<T,U>{
+    fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        self.call_action_with_context(input, arguments, &mut EvalContext::new())
+    }
+    fn call_action_with_context(&self, input:T, arguments:&Vec<ActionParameter>, context:&mut EvalContext) -> Result<T, Error>{
+        let input = U::from_bytes(&input.as_bytes(&self.format)?, &self.format)?;
+        let output = self.action.call_action_with_context(input, arguments, context)?;
+        T::from_bytes(&output.as_bytes(&self.format)?, &self.format)
+    }
+    fn is_loader(&self)->bool{
+        self.action.is_loader()
+    }
+    fn expected_param_count(&self)->Option<usize>{
+        self.action.expected_param_count()
+    }
+}
+
+/// Function of the input value (see ValueLike for the requirements on the value type).
 pub struct Function1<In,Out>(pub Box<dyn Fn(In)->Out /*+ Send*/>);
+/// Function of the input value and one parameter.
 pub struct Function2<In1,In2,Out>(pub Box<dyn Fn(In1,In2)->Out /*+ Send*/>);
+/// Function of the input value and two parameters.
+pub struct Function3<In1,In2,In3,Out>(pub Box<dyn Fn(In1,In2,In3)->Out /*+ Send*/>);
 /*
 fn call1<T,In,Out>(f:Function1<In,Out>,input:T)->Result<T, Error>
 where
@@ -66,152 +191,1821 @@ Out:Into<T>
     Ok(f.0(f_input).into())
 }
 */
+/// Type identifier (as in `ValueSerializer::type_identifier`) of a function input type,
+/// used to name the expected type in the input conversion errors.
+pub trait InputType{
+    fn input_type_identifier()->&'static str;
+}
+
+impl InputType for i32{
+    fn input_type_identifier()->&'static str{"int"}
+}
+
+impl InputType for f64{
+    fn input_type_identifier()->&'static str{"real"}
+}
+
+impl InputType for bool{
+    fn input_type_identifier()->&'static str{"bool"}
+}
+
+impl InputType for String{
+    fn input_type_identifier()->&'static str{"text"}
+}
+
+impl InputType for Vec<u8>{
+    fn input_type_identifier()->&'static str{"bytes"}
+}
+
+impl InputType for Value{
+    fn input_type_identifier()->&'static str{"any"}
+}
+
+/// Convert the action input, the error message names both the expected and the actual type.
+fn convert_input<T,In>(input:T)->Result<In,Error>
+where
+    T:TryInto<In> + ValueSerializer,
+    In:InputType,
+    <T as std::convert::TryInto<In>>::Error:Display
+    {
+    let input_type = input.type_identifier();
+    input.try_into()
+    .map_err(|e|
+        Error::ConversionError{message:format!("Input argument conversion failed; expected {} input, got {}; {}",
+            In::input_type_identifier(), input_type, e)})
+}
+
 impl<T,In,Out> CallableAction<T> for Function1<In,Out>
 where
-    T:TryInto<In>,
+    T:TryInto<In> + ValueSerializer,
+    In:InputType,
     Out:Into<T>,
     <T as std::convert::TryInto<In>>::Error:Display
     {
     fn call_action(&self, input:T, _arguments:&Vec<ActionParameter>) -> Result<T, Error>{
-        let f_input:In = input.try_into()
-        .map_err(|e|
-            Error::ConversionError{message:format!("Input argument conversion failed; {}",e)})?;
+        let f_input:In = convert_input(input)?;
 
         let out:Out = self.0(f_input);
         let result:T = out.into();
         Ok(result)
     }
+    fn expected_param_count(&self)->Option<usize>{
+        Some(0)
+    }
+}
+
+/// Index of a missing argument of a function action, counting the input as argument 0
+/// (the parameter index reported by ActionParametersSlice is shifted by one).
+fn count_input_argument(e:Error)->Error{
+    match e{
+        Error::ArgumentNotSpecified{index, action} => Error::ArgumentNotSpecified{index:index+1, action},
+        e => e
+    }
 }
 
 impl<T,In1,In2,Out> CallableAction<T> for Function2<In1,In2,Out>
 where
-    T:TryInto<In1>,
+    T:TryInto<In1> + ValueSerializer,
+    In1:InputType,
     In2: TryParameterFrom,
     Out:Into<T>,
     <T as std::convert::TryInto<In1>>::Error:Display
     {
     fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
-        let a1:In1 = input.try_into()
-        .map_err(|e|
-            Error::ConversionError{message:format!("Input argument conversion failed; {}",e)})?;
-        let mut par = ActionParametersSlice(&arguments[..]);
-        let a2:In2 =  par.try_parameters_into(&mut ())?;
+        let a1:In1 = convert_input(input)?;
+        let mut par = ActionParametersSlice::new(&arguments[..]);
+        let a2:In2 =  par.try_parameters_into(&mut ()).map_err(count_input_argument)?;
         let out:Out = self.0(a1, a2);
         let result:T = out.into();
         Ok(result)
     }
+    fn expected_param_count(&self)->Option<usize>{
+        Some(1)
+    }
 }
 
-pub struct HashMapActionRegistry<T>(
-    HashMap<
-        String,
-        HashMap<String, Box<dyn CallableAction<T> /*+ Send*/>>  
-    >
-);
+impl<T,In1,In2,In3,Out> CallableAction<T> for Function3<In1,In2,In3,Out>
+where
+    T:TryInto<In1> + ValueSerializer,
+    In1:InputType,
+    In2: TryParameterFrom,
+    In3: TryParameterFrom,
+    Out:Into<T>,
+    <T as std::convert::TryInto<In1>>::Error:Display
+    {
+    fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        let a1:In1 = convert_input(input)?;
+        let mut par = ActionParametersSlice::new(&arguments[..]);
+        let a2:In2 =  par.try_parameters_into(&mut ()).map_err(count_input_argument)?;
+        let a3:In3 =  par.try_parameters_into(&mut ()).map_err(count_input_argument)?;
+        let out:Out = self.0(a1, a2, a3);
+        let result:T = out.into();
+        Ok(result)
+    }
+    fn expected_param_count(&self)->Option<usize>{
+        Some(2)
+    }
+}
 
-impl<T> HashMapActionRegistry<T>{
-    pub fn new()->Self{
-        HashMapActionRegistry::<T>(HashMap::new())
+/// Action selecting a single output of an action returning multiple outputs.
+/// Actions with multiple outputs return them as a Value::List;
+/// the parameter of the item action is the (zero-based) index of the output, e.g. item-0.
+pub struct ItemAction;
+
+impl CallableAction<Value> for ItemAction{
+    fn call_action(&self, input:Value, arguments:&Vec<ActionParameter>) -> Result<Value, Error>{
+        let mut par = ActionParametersSlice::new(&arguments[..]);
+        let index:i32 = par.try_parameters_into(&mut ())?;
+        match input{
+            Value::List(items) => items.into_iter().nth(index as usize)
+                .ok_or_else(|| Error::General{message:format!("Item {} not available",index)}),
+            x => Err(Error::ConversionError{message:format!("Can't select item from {}", x.type_identifier())})
+        }
     }
+    fn expected_param_count(&self)->Option<usize>{
+        Some(1)
+    }
+}
 
-    pub fn register_callable_action(&mut self, ns:&str, name:&str, action:Box<dyn CallableAction<T> /*+ Send*/>){
-        let ns = ns.to_owned();
-        let name = name.to_owned();
-        let ns_registry = self.0.entry(ns).or_insert(HashMap::new());
-        ns_registry.insert(name, action);
+/// Action returning the type identifier of the input value as text.
+pub struct TypeOfAction;
+
+impl<T> CallableAction<T> for TypeOfAction
+where
+    T:ValueSerializer + From<String>
+    {
+    fn call_action(&self, input:T, _arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        Ok(T::from(input.type_identifier()))
+    }
+    fn expected_param_count(&self)->Option<usize>{
+        Some(0)
     }
+}
 
-    pub fn call(&self, ns:&str, name:&str, input:T, arguments:&Vec<ActionParameter>)->Result<T, Error>{
-        self.0.get(ns)
-        .ok_or_else(|| Error::ActionNotRegistered{message:format!("Action {} not registered in namespace {}; no such namespace",name,ns)})
-        .and_then(
-            |ns_registry|
-            ns_registry.get(name)
-            .ok_or_else(|| Error::ActionNotRegistered{message:format!("Action {} not registered in namespace {}",name,ns)})
-        )?.call_action(input, arguments)
+/// Create the action returning the type identifier of the input value (see TypeOfAction).
+pub fn type_of_action<T:ValueSerializer + From<String>>()->Box<dyn CallableAction<T>>{
+    Box::new(TypeOfAction)
+}
+
+/// Action returning the first element of a list.
+pub struct HeadAction;
+
+impl<T> CallableAction<T> for HeadAction
+where
+    T:TryInto<Vec<T>, Error=Error>
+    {
+    fn call_action(&self, input:T, _arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        input.try_into()?.into_iter().next()
+        .ok_or_else(|| Error::General{message:"Can't take head of an empty list".to_owned()})
+    }
+    fn expected_param_count(&self)->Option<usize>{
+        Some(0)
     }
 }
 
-impl<T> Environment<T> for HashMapActionRegistry<T>{
-    fn eval(&mut self, input:T, query:&str)->Result<T,Error>{
-        let path = parse_query_simple(query)?;
+/// Create the action returning the first element of a list (see HeadAction).
+pub fn head_action<T:TryInto<Vec<T>, Error=Error>>()->Box<dyn CallableAction<T>>{
+    Box::new(HeadAction)
+}
 
-        let mut value = input;
-        for action_request in path{
-            value = self.call("root", &action_request.name, value, &action_request.parameters)?
+/// Action returning a list without its first element.
+pub struct TailAction;
+
+impl<T> CallableAction<T> for TailAction
+where
+    T:TryInto<Vec<T>, Error=Error> + From<Vec<T>>
+    {
+    fn call_action(&self, input:T, _arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        let list:Vec<T> = input.try_into()?;
+        if list.is_empty(){
+            return Err(Error::General{message:"Can't take tail of an empty list".to_owned()});
         }
-        Ok(value)
+        Ok(T::from(list.into_iter().skip(1).collect()))
+    }
+    fn expected_param_count(&self)->Option<usize>{
+        Some(0)
     }
 }
 
+/// Create the action returning a list without its first element (see TailAction).
+pub fn tail_action<T:TryInto<Vec<T>, Error=Error> + From<Vec<T>>>()->Box<dyn CallableAction<T>>{
+    Box::new(TailAction)
+}
 
-#[cfg(test)]
-mod tests{
-    use super::*;
-    use crate::value::*;
+/// Action serializing the input value into bytes in the format given by the parameter, e.g. to_bytes-json.
+/// The bytes are returned as a value deserialized with the identity binary codec ("b"), i.e. Value::Bytes.
+pub struct SerializeAction;
 
-    #[test]
-    fn closure_call_action()-> Result<(), Box<dyn std::error::Error>>{
-        let a = |x:i32| x*x;
-        let result = a.call_action(Value::Integer(2),&vec![])?;
-        assert_eq!(result, Value::Integer(4));
-        Ok(())
+impl<T> CallableAction<T> for SerializeAction
+where
+    T:ValueSerializer
+    {
+    fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        let mut par = ActionParametersSlice::new(&arguments[..]);
+        let format:String = par.try_parameters_into(&mut ())?;
+        T::from_bytes(&input.as_bytes(&format)?, "b")
+    }
+    fn expected_param_count(&self)->Option<usize>{
+        Some(1)
     }
+}
 
-    #[test]
-    fn function1_call_action()-> Result<(), Box<dyn std::error::Error>>{
-        let a = |x:i32| x*x;
-        //let f:Function1<i32,i32> = Function1(Box::new(a));
-        let result = Function1(Box::new(a)).call_action(Value::Integer(2),&vec![])?;
-        assert_eq!(result, Value::Integer(4));
-        Ok(())
+/// Create the action serializing the input value (see SerializeAction).
+pub fn serialize_action<T:ValueSerializer>()->Box<dyn CallableAction<T>>{
+    Box::new(SerializeAction)
+}
+
+/// Loader producing a literal value from its parameter, e.g. `value-42/square`.
+/// The parameter is converted to the most specific type it can be parsed as (with TryParameterFrom):
+/// integer, real number, bool, otherwise text.
+pub struct LiteralAction;
+
+impl<T> CallableAction<T> for LiteralAction
+where
+    T:From<i32> + From<f64> + From<bool> + From<String>
+    {
+    fn call_action(&self, _input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        let mut par = ActionParametersSlice::new(&arguments[..]);
+        let text:String = par.try_parameters_into(&mut ())?;
+        if let Ok(x) = i32::try_parameter_from(&text){
+            Ok(T::from(x))
+        }
+        else if let Ok(x) = f64::try_parameter_from(&text){
+            Ok(T::from(x))
+        }
+        else if let Ok(x) = bool::try_parameter_from(&text){
+            Ok(T::from(x))
+        }
+        else{
+            Ok(T::from(text))
+        }
     }
-    #[test]
-    fn function2_call_action()-> Result<(), Box<dyn std::error::Error>>{
-        let a = |x:i32,y:i32| x*y;
-        //let f:Function1<i32,i32> = Function1(Box::new(a));
-        let result = Function2(Box::new(a)).call_action(Value::Integer(2),&vec![ActionParameter::new("3")])?;
-        assert_eq!(result, Value::Integer(6));
-        Ok(())
+    fn is_loader(&self)->bool{
+        true
     }
-    /*
-    #[test]
-    fn test3()-> Result<(), Box<dyn std::error::Error>>{
-        let a = |x:i32| x*x;
-        let f:Function1<i32,i32> = Function1(Box::new(a));
-        assert_eq!(call1(f,Value::Integer(2))?, Value::Integer(4));
-        Ok(())
+    fn expected_param_count(&self)->Option<usize>{
+        Some(1)
     }
-    */
-    #[test]
-    fn test_registry1()->Result<(),Box<dyn std::error::Error>>{
-        let mut registry = HashMapActionRegistry::<Value>::new();
-        let a = |x:i32| x*x;
-        registry.register_callable_action("root", "test", Box::new(Function1(Box::new(a))));
-        let result = registry.call("root", "test", Value::Integer(2), &vec![])?;
-        assert_eq!(result, Value::Integer(4));
-        Ok(())   
+}
+
+/// Create the loader producing a literal value from its parameter (see LiteralAction).
+pub fn literal_action<T:From<i32> + From<f64> + From<bool> + From<String>>()->Box<dyn CallableAction<T>>{
+    Box::new(LiteralAction)
+}
+
+/// Loader - an action, which ignores the input and produces a value from a resource specified by its parameters,
+/// e.g. `load-data-x`. When a query starts with a loader, the evaluation starts from the default value
+/// (Value::None) instead of the input, and the loader produces the initial value of the pipeline.
+pub struct Loader<T>(pub Box<dyn CallableAction<T>>);
+
+impl<T> CallableAction<T> for Loader<T>{
+    fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        self.0.call_action(input, arguments)
     }
-    #[test]
-    fn test_eval()->Result<(),Box<dyn std::error::Error>>{
-        let mut registry = HashMapActionRegistry::<Value>::new();
-        let square = |x:i32| x*x;
-        let add = |x:i32,y:i32| x+y;
-        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
-        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
-        let result = registry.eval(Value::Integer(2),"square/add-10")?;
-        assert_eq!(result, Value::Integer(14));
-        Ok(())   
+    fn call_action_with_context(&self, input:T, arguments:&Vec<ActionParameter>, context:&mut EvalContext) -> Result<T, Error>{
+        self.0.call_action_with_context(input, arguments, context)
     }
+    fn call_action_stream<'a>(&'a self, input:T, arguments:&Vec<ActionParameter>, context:&mut EvalContext) -> Result<ValueStream<'a,T>, Error>{
+        self.0.call_action_stream(input, arguments, context)
+    }
+    fn is_loader(&self)->bool{
+        true
+    }
+    fn expected_param_count(&self)->Option<usize>{
+        self.0.expected_param_count()
+    }
+}
 
-    #[test]
-    fn test_hello()->Result<(),Box<dyn std::error::Error>>{
-        let mut registry = HashMapActionRegistry::<Value>::new();
-        let hello = |x:String| format!("Hello, {}!",x);
-        registry.register_callable_action("root", "hello", Box::new(Function1(Box::new(hello))));
-        let result = registry.eval(Value::Text("world".to_owned()),"hello")?;
-        assert_eq!(result, Value::Text("Hello, world!".to_owned()));
-        Ok(())   
+/// Action shared between multiple registrations (e.g. registered under several names).
+struct SharedAction<T>(Rc<dyn CallableAction<T>>);
+
+impl<T> CallableAction<T> for SharedAction<T>{
+    fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        self.0.call_action(input, arguments)
+    }
+    fn call_action_with_context(&self, input:T, arguments:&Vec<ActionParameter>, context:&mut EvalContext) -> Result<T, Error>{
+        self.0.call_action_with_context(input, arguments, context)
+    }
+    fn call_action_stream<'a>(&'a self, input:T, arguments:&Vec<ActionParameter>, context:&mut EvalContext) -> Result<ValueStream<'a,T>, Error>{
+        self.0.call_action_stream(input, arguments, context)
+    }
+    fn is_loader(&self)->bool{
+        self.0.is_loader()
+    }
+    fn expected_param_count(&self)->Option<usize>{
+        self.0.expected_param_count()
+    }
+}
+
+/// Declaration of an action in a configuration:
+/// the action name is mapped to a built-in operation (see BuiltinTable).
+/// Parameters not specified in the query are taken from the default parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ActionSpec{
+    pub name:String,
+    pub op:String,
+    #[serde(default)]
+    pub parameters:Vec<String>,
+}
+
+/// Table of the built-in operations, which can be referenced by ActionSpec.
+pub struct BuiltinTable<T>(HashMap<String, Rc<dyn CallableAction<T>>>);
+
+impl<T> BuiltinTable<T>{
+    pub fn new()->Self{
+        BuiltinTable(HashMap::new())
+    }
+    pub fn register(&mut self, op:&str, action:Box<dyn CallableAction<T>>){
+        self.0.insert(op.to_owned(), Rc::from(action));
+    }
+    pub fn get(&self, op:&str)->Option<&Rc<dyn CallableAction<T>>>{
+        self.0.get(op)
+    }
+}
+
+impl<T> Default for BuiltinTable<T>{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Built-in operation with the default parameters from an ActionSpec.
+struct SpecAction<T>{
+    action:Rc<dyn CallableAction<T>>,
+    defaults:Vec<ActionParameter>
+}
+
+impl<T> SpecAction<T>{
+    fn arguments(&self, arguments:&[ActionParameter])->Vec<ActionParameter>{
+        let mut arguments = arguments.to_vec();
+        if arguments.len() < self.defaults.len(){
+            arguments.extend_from_slice(&self.defaults[arguments.len()..]);
+        }
+        arguments
+    }
+}
+
+impl<T> CallableAction<T> for SpecAction<T>{
+    fn call_action(&self, input:T, arguments:&Vec<ActionParameter>) -> Result<T, Error>{
+        self.action.call_action(input, &self.arguments(arguments))
+    }
+    fn call_action_with_context(&self, input:T, arguments:&Vec<ActionParameter>, context:&mut EvalContext) -> Result<T, Error>{
+        self.action.call_action_with_context(input, &self.arguments(arguments), context)
+    }
+    fn call_action_stream<'a>(&'a self, input:T, arguments:&Vec<ActionParameter>, context:&mut EvalContext) -> Result<ValueStream<'a,T>, Error>{
+        self.action.call_action_stream(input, &self.arguments(arguments), context)
+    }
+    fn is_loader(&self)->bool{
+        self.action.is_loader()
+    }
+    /// With default parameters the count is not fixed.
+    fn expected_param_count(&self)->Option<usize>{
+        if self.defaults.is_empty(){
+            self.action.expected_param_count()
+        }
+        else{
+            None
+        }
+    }
+}
+
+/// Match name against a pattern, where `*` matches any (possibly empty) sequence of characters.
+fn glob_match(pattern:&str, name:&str)->bool{
+    let parts:Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1{
+        return pattern == name;
+    }
+    let (first, last) = (parts[0], parts[parts.len()-1]);
+    if name.len() < first.len() + last.len() || !name.starts_with(first) || !name.ends_with(last){
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len()-last.len()];
+    for part in parts[1..parts.len()-1].iter(){
+        match rest.find(part){
+            Some(i) => rest = &rest[i+part.len()..],
+            None => return false
+        }
+    }
+    true
+}
+
+/// Description of a registered action.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ActionMetadata{
+    /// Media types the action may produce (as far as it is known)
+    #[serde(default)]
+    pub output_media_types:Vec<String>,
+    /// Short human-readable description of the action (see explain)
+    #[serde(default)]
+    pub doc:String,
+    /// Declared parameters; if present, string parameters are checked by compile before the execution
+    #[serde(default)]
+    pub parameters:Vec<ParamInfo>,
+    /// Type identifier of the values the action produces (see ValueSerializer::type_identifier), if known;
+    /// PASS_THROUGH_TYPE if the action produces the type of its input (e.g. head)
+    #[serde(default)]
+    pub output_type:Option<String>,
+    /// Type identifier of the input the action accepts, if restricted (see infer_output_type)
+    #[serde(default)]
+    pub input_type:Option<String>,
+}
+
+/// Declared output type of actions producing a value of the same type as their input.
+pub const PASS_THROUGH_TYPE:&str = "*";
+
+/// Type of an action parameter, see ParamInfo.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType{
+    Int,
+    Real,
+    Bool,
+    Text,
+}
+
+impl ParamType{
+    /// Name of the type as used in the messages (int, real, bool or text).
+    pub fn name(&self)->&'static str{
+        match self{
+            ParamType::Int => "int",
+            ParamType::Real => "real",
+            ParamType::Bool => "bool",
+            ParamType::Text => "text",
+        }
+    }
+    /// True if the parameter text can be converted to the type (with TryParameterFrom).
+    pub fn accepts(&self, text:&str)->bool{
+        match self{
+            ParamType::Int => i32::try_parameter_from(text).is_ok(),
+            ParamType::Real => f64::try_parameter_from(text).is_ok(),
+            ParamType::Bool => bool::try_parameter_from(text).is_ok(),
+            ParamType::Text => true,
+        }
     }
+}
 
+/// Description of an action parameter.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParamInfo{
+    pub name:String,
+    pub param_type:ParamType,
+}
+
+/// Change of the registry content, reported to the observers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryEvent{
+    Registered{ns:String, name:String},
+    Unregistered{ns:String, name:String},
+}
+
+/// Observer of the registry changes.
+pub type RegistryObserver = Box<dyn FnMut(&RegistryEvent)>;
+
+pub struct HashMapActionRegistry<T>{
+    actions:HashMap<
+        String,
+        HashMap<String, Box<dyn CallableAction<T> /*+ Send*/>>  
+    >,
+    observers:Vec<RegistryObserver>,
+    metadata:HashMap<String, HashMap<String, ActionMetadata>>
+}
+
+impl<T> HashMapActionRegistry<T>{
+    pub fn new()->Self{
+        HashMapActionRegistry::<T>{actions:HashMap::new(), observers:Vec::new(), metadata:HashMap::new()}
+    }
+
+    /// Register an observer, which is notified whenever an action is registered or unregistered.
+    pub fn on_change(&mut self, observer:RegistryObserver){
+        self.observers.push(observer);
+    }
+
+    fn notify(&mut self, event:RegistryEvent){
+        for observer in self.observers.iter_mut(){
+            observer(&event);
+        }
+    }
+
+    pub fn register_callable_action(&mut self, ns:&str, name:&str, action:Box<dyn CallableAction<T> /*+ Send*/>){
+        let ns_registry = self.actions.entry(ns.to_owned()).or_insert(HashMap::new());
+        ns_registry.insert(name.to_owned(), action);
+        self.notify(RegistryEvent::Registered{ns:ns.to_owned(), name:name.to_owned()});
+    }
+
+    /// Remove an action from the registry; returns the removed action (if it was registered).
+    pub fn unregister_callable_action(&mut self, ns:&str, name:&str)->Option<Box<dyn CallableAction<T> /*+ Send*/>>{
+        let action = self.actions.get_mut(ns).and_then(|ns_registry| ns_registry.remove(name))?;
+        if let Some(ns_metadata) = self.metadata.get_mut(ns){
+            ns_metadata.remove(name);
+        }
+        self.notify(RegistryEvent::Unregistered{ns:ns.to_owned(), name:name.to_owned()});
+        Some(action)
+    }
+
+    /// Register a single action under multiple names (synonyms).
+    pub fn register_callable_action_names(&mut self, ns:&str, names:&[&str], action:Box<dyn CallableAction<T> /*+ Send*/>)
+    where T:'static
+    {
+        let shared:Rc<dyn CallableAction<T>> = Rc::from(action);
+        for name in names{
+            self.register_callable_action(ns, name, Box::new(SharedAction(shared.clone())));
+        }
+    }
+
+    pub fn get_callable_action(&self, ns:&str, name:&str)->Option<&dyn CallableAction<T>>{
+        self.actions.get(ns).and_then(|ns_registry| ns_registry.get(name)).map(|action| action.as_ref())
+    }
+
+    /// Set the metadata describing an action.
+    pub fn register_action_metadata(&mut self, ns:&str, name:&str, metadata:ActionMetadata){
+        self.metadata.entry(ns.to_owned()).or_default().insert(name.to_owned(), metadata);
+    }
+
+    pub fn get_action_metadata(&self, ns:&str, name:&str)->Option<&ActionMetadata>{
+        self.metadata.get(ns).and_then(|ns_metadata| ns_metadata.get(name))
+    }
+
+    /// Declare the type identifier of the values produced by an action (stored in the action metadata);
+    /// PASS_THROUGH_TYPE declares that the action produces the type of its input.
+    pub fn register_output_type(&mut self, ns:&str, name:&str, output_type:&str){
+        self.metadata.entry(ns.to_owned()).or_default().entry(name.to_owned()).or_default()
+        .output_type = Some(output_type.to_owned());
+    }
+
+    /// Declare the type identifier of the input accepted by an action (stored in the action metadata).
+    pub fn register_input_type(&mut self, ns:&str, name:&str, input_type:&str){
+        self.metadata.entry(ns.to_owned()).or_default().entry(name.to_owned()).or_default()
+        .input_type = Some(input_type.to_owned());
+    }
+
+    /// Type identifier of the query result inferred by threading the declared types through the actions:
+    /// each action produces its declared output type, an action with PASS_THROUGH_TYPE output keeps the type
+    /// of its input and an action without declared output type makes the type unknown.
+    /// None if the type is unknown or the query has no actions; also None if a known type doesn't match
+    /// the declared input type of the action receiving it, since such a query can't be evaluated.
+    pub fn infer_output_type(&self, query:&Query)->Option<String>{
+        let mut current:Option<String> = None;
+        for action in query.segments.iter().flat_map(|segment| segment.query.iter()){
+            let metadata = self.get_action_metadata("root", &action.name);
+            let input_type = metadata.and_then(|metadata| metadata.input_type.as_ref());
+            if let (Some(current), Some(input_type)) = (&current, input_type){
+                if current != input_type{
+                    return None;
+                }
+            }
+            current = match metadata.and_then(|metadata| metadata.output_type.as_ref()){
+                Some(output_type) if output_type == PASS_THROUGH_TYPE => current,
+                output_type => output_type.cloned()
+            };
+        }
+        current
+    }
+
+    /// Media types the query may produce: determined by the filename extension if present,
+    /// otherwise taken from the metadata of the last action. Empty if unknown.
+    pub fn output_media_types(&self, query:&Query)->Vec<String>{
+        if let Some(extension) = query.extension(){
+            // Compound extensions (e.g. serde.json) are identified by the last part
+            let extension = extension.rsplit('.').next().unwrap_or(extension);
+            return vec![media_type_from_extension(extension).to_owned()];
+        }
+        query.segments.iter().rev()
+        .find_map(|segment| segment.query.last())
+        .and_then(|action| self.get_action_metadata("root", &action.name))
+        .map(|metadata| metadata.output_media_types.clone())
+        .unwrap_or_default()
+    }
+
+    /// Human-readable plan of the query: one line per action with its name, namespace, parameters
+    /// (as encoded in the query) and the doc from the action metadata, plus a line per non-minimal segment header.
+    /// Unregistered actions and parameter count mismatches are flagged in the line rather than reported as errors,
+    /// so that the whole query can be inspected; only a query, which can't be parsed, is an error.
+    pub fn explain(&self, query:&str)->Result<String, Error>{
+        let ns = "root";
+        let mut lines = Vec::new();
+        for segment in parse(query)?.segments{
+            if let Some(header) = segment.header.as_ref().filter(|header| !header.name.is_empty()){
+                lines.push(format!("header {}", header.encode()));
+            }
+            for action_request in segment.query{
+                let parameters:Vec<String> = action_request.parameters.iter().map(|x| x.encode()).collect();
+                let mut line = format!("{} [{}] ({})", action_request.name, ns, parameters.join(", "));
+                match self.get_callable_action(ns, &action_request.name){
+                    Some(action) => {
+                        if let Some(count) = action.expected_param_count().filter(|count| *count != parameters.len()){
+                            line.push_str(&format!(" !! expects {} parameter(s), {} given", count, parameters.len()));
+                        }
+                        if let Some(metadata) = self.get_action_metadata(ns, &action_request.name).filter(|x| !x.doc.is_empty()){
+                            line.push_str(&format!(" - {}", metadata.doc));
+                        }
+                    },
+                    None => line.push_str(" !! not registered"),
+                }
+                lines.push(line);
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Names of the actions in the namespace matching the pattern (sorted);
+    /// the pattern may contain `*` matching any sequence of characters, e.g. `plot_*`.
+    pub fn find(&self, ns:&str, pattern:&str)->Vec<&str>{
+        let mut names:Vec<&str> = self.actions.get(ns)
+        .map(|ns_registry| ns_registry.keys().map(|x| x.as_str()).filter(|name| glob_match(pattern, name)).collect())
+        .unwrap_or_default();
+        names.sort_unstable();
+        names
+    }
+
+    /// Register a loader (see Loader).
+    pub fn register_loader(&mut self, ns:&str, name:&str, action:Box<dyn CallableAction<T> /*+ Send*/>)
+    where T:'static
+    {
+        self.register_callable_action(ns, name, Box::new(Loader(action)));
+    }
+
+    pub fn call(&self, ns:&str, name:&str, input:T, arguments:&Vec<ActionParameter>)->Result<T, Error>{
+        self.actions.get(ns)
+        .ok_or_else(|| Error::ActionNotRegistered{message:format!("Action {} not registered in namespace {}; no such namespace",name,ns)})
+        .and_then(
+            |ns_registry|
+            ns_registry.get(name)
+            .ok_or_else(|| Error::ActionNotRegistered{message:format!("Action {} not registered in namespace {}",name,ns)})
+        )?.call_action(input, arguments)
+        .map_err(|e| e.with_action(name))
+    }
+
+    /// Call action registered under the name in any namespace (e.g. when the namespace of a plugin is not known).
+    /// Fails if the action is not registered in any namespace
+    /// or if it is ambiguous (registered in several namespaces); the error lists the candidate namespaces.
+    pub fn call_any(&self, name:&str, input:T, arguments:&Vec<ActionParameter>)->Result<T, Error>{
+        let mut namespaces:Vec<&str> = self.actions.iter()
+        .filter(|(_, ns_registry)| ns_registry.contains_key(name))
+        .map(|(ns, _)| ns.as_str())
+        .collect();
+        namespaces.sort_unstable();
+        match &namespaces[..]{
+            [] => Err(Error::ActionNotRegistered{message:format!("Action {} not registered in any namespace",name)}),
+            [ns] => self.call(ns, name, input, arguments),
+            _ => Err(Error::General{message:format!("Action {} is ambiguous; registered in namespaces {}",name,namespaces.join(", "))})
+        }
+    }
+
+    pub fn call_with_context(&self, ns:&str, name:&str, input:T, arguments:&Vec<ActionParameter>, context:&mut EvalContext)->Result<T, Error>{
+        self.get_callable_action(ns, name)
+        .ok_or_else(|| Error::ActionNotRegistered{message:format!("Action {} not registered in namespace {}",name,ns)})?
+        .call_action_with_context(input, arguments, context)
+        .map_err(|e| e.with_action(name))
+    }
+}
+
+impl<T:'static> HashMapActionRegistry<T>{
+    /// Build registry (root namespace) from the action specifications; fails if a spec refers to an unknown operation.
+    pub fn from_specs(specs:&[ActionSpec], builtins:&BuiltinTable<T>)->Result<Self, Error>{
+        let mut registry = Self::new();
+        for spec in specs.iter(){
+            let action = builtins.get(&spec.op)
+            .ok_or_else(|| Error::General{message:format!("Unknown operation {} in the specification of action {}", spec.op, spec.name)})?;
+            let defaults = spec.parameters.iter().map(|x| ActionParameter::new(x)).collect();
+            registry.register_callable_action("root", &spec.name, Box::new(SpecAction{action:action.clone(), defaults}));
+        }
+        Ok(registry)
+    }
+}
+
+impl<T> HashMapActionRegistry<T>
+where
+    T:Clone + Default + TryInto<String>,
+    <T as std::convert::TryInto<String>>::Error:Display
+    {
+    /// Evaluate query; stack contains the queries which are currently being evaluated
+    /// and is used to detect cyclic references via links.
+    /// Note that a link is always shorter than the query containing it, so as long as there is no indirection
+    /// (such as named or constant queries) resolving to a query, a cycle can't be reached from `eval`;
+    /// the check guards the evaluation of links against such indirections.
+    fn eval_with_stack(&self, input:T, query:&str, stack:&mut Vec<String>, context:&mut EvalContext)->Result<T,Error>{
+        if stack.iter().any(|x| x==query){
+            return Err(Error::General{message:format!("cyclic query reference: {}",query)});
+        }
+        stack.push(query.to_owned());
+        let result = parse(query).and_then(|query|{
+            let mut value = if self.starts_with_loader(&query) {T::default()} else {input};
+            for segment in query.segments{
+                if let Some(header) = &segment.header{
+                    self.apply_header(&value, header, context)?;
+                }
+                for action_request in segment.query{
+                    let parameters = self.resolve_parameters(&action_request.parameters, stack, context)?;
+                    value = self.call_with_context("root", &action_request.name, value, &parameters, context)?
+                }
+            }
+            Ok(value)
+        });
+        stack.pop();
+        result
+    }
+
+    /// True if the query starts with a loader; the evaluation then starts from the default value.
+    fn starts_with_loader(&self, query:&Query)->bool{
+        query.segments.first()
+        .filter(|segment| segment.header.is_none())
+        .and_then(|segment| segment.query.first())
+        .and_then(|action_request| self.get_callable_action("root", &action_request.name))
+        .is_some_and(|action| action.is_loader())
+    }
+
+    /// Apply segment header to the evaluation; the value is the result of the preceding segments.
+    /// Supported headers are the minimal header `-` (no effect) and `-as-<name>`,
+    /// which stores the value (as text) in the context variables under the name.
+    /// The variables are visible to all the following actions of the evaluation (including the queries in links)
+    /// and can be referenced as `~V~name~E` parameters; naming a value again replaces it.
+    /// Since variables are only used as parameters, only values convertible to text can be named
+    /// (e.g. naming a list fails with a conversion error).
+    fn apply_header(&self, value:&T, header:&SegmentHeader, context:&mut EvalContext)->Result<(),Error>{
+        check_header(header)?;
+        if header.name == "as"{
+            let name = header.parameters[0].to_string();
+            let text:String = value.clone().try_into()
+            .map_err(|e| Error::ConversionError{message:format!("Can't store result as {}; {}", name, e)})?;
+            context.variables.insert(name, Value::Text(text));
+        }
+        Ok(())
+    }
+
+    /// Resolve links and variables in parameters.
+    /// A link is evaluated (starting with the default value) and the result is passed to the action as text.
+    /// A variable is replaced by its value (see apply_header) converted to text.
+    /// The stack is used to detect cyclic references; resolving a link to a query, which is already being evaluated,
+    /// fails with an error instead of an infinite recursion.
+    pub fn resolve_parameters(&self, parameters:&[ActionParameter], stack:&mut Vec<String>, context:&mut EvalContext)->Result<Vec<ActionParameter>,Error>{
+        parameters.iter().map(|parameter|
+            match parameter{
+                ActionParameter::Link(query, position) => {
+                    let value:String = self.eval_with_stack(T::default(), query, stack, context)?
+                    .try_into()
+                    .map_err(|e| Error::ParameterError{message:format!("Link conversion failed; {}",e), position:position.clone()})?;
+                    Ok(ActionParameter::new_parsed(value, position.clone()))
+                },
+                ActionParameter::Variable(name, position) => {
+                    let value = context.variables.get(name)
+                    .ok_or_else(|| Error::ParameterError{message:format!("Undefined variable {}",name), position:position.clone()})?;
+                    let value = String::try_from(value.clone())
+                    .map_err(|e| Error::ParameterError{message:format!("Variable {} conversion failed; {}",name,e), position:position.clone()})?;
+                    Ok(ActionParameter::new_parsed(value, position.clone()))
+                },
+                x => Ok(x.clone())
+            }
+        ).collect()
+    }
+
+    /// Evaluate query within the evaluation context.
+    pub fn eval_query(&self, input:T, query:&str, context:&mut EvalContext)->Result<T,Error>{
+        self.eval_with_stack(input, query, &mut vec![], context)
+    }
+
+    /// Evaluate query, yielding the elements of the resulting list as they are produced by the last action,
+    /// so that a large list doesn't need to be materialized.
+    /// The query must end with an action supporting chunked output (see ChunkedAction); all the preceding
+    /// actions are evaluated as usual. Errors while producing the elements are yielded by the iterator.
+    pub fn eval_stream(&self, input:T, query:&str, context:&mut EvalContext)->Result<ValueStream<'_,T>,Error>{
+        let mut query = parse(query)?;
+        let action_request = query.segments.last_mut().and_then(|segment| segment.query.pop())
+        .ok_or_else(|| Error::General{message:"Streaming evaluation requires a query ending with an action".to_owned()})?;
+        let action = self.get_callable_action("root", &action_request.name)
+        .ok_or_else(|| Error::ActionNotRegistered{message:format!("Action {} not registered in namespace root",action_request.name)})?;
+        let value = self.eval_with_stack(input, &query.encode(), &mut vec![], context)?;
+        let value = if action.is_loader() {T::default()} else {value};
+        let parameters = self.resolve_parameters(&action_request.parameters, &mut vec![], context)?;
+        action.call_action_stream(value, &parameters, context)
+        .map_err(|e| e.with_action(&action_request.name))
+    }
+
+    /// Evaluate query, recording a trace of the evaluation for debugging.
+    /// The trace is a JSON array with an object per action (links resolved in parameters are not traced):
+    /// `action` (the action as encoded in the query), `input_type`, `output_type` (type identifiers)
+    /// and `duration_us` (duration of the action call in microseconds).
+    pub fn eval_traced(&self, input:T, query:&str)->Result<(T, serde_json::Value),Error>
+    where T:ValueSerializer
+    {
+        let query = parse(query)?;
+        let mut context = EvalContext::new();
+        let mut trace = Vec::new();
+        let mut value = if self.starts_with_loader(&query) {T::default()} else {input};
+        for segment in query.segments{
+            if let Some(header) = &segment.header{
+                self.apply_header(&value, header, &mut context)?;
+            }
+            for action_request in segment.query{
+                let parameters = self.resolve_parameters(&action_request.parameters, &mut vec![], &mut context)?;
+                let input_type = value.type_identifier();
+                let start = Instant::now();
+                value = self.call_with_context("root", &action_request.name, value, &parameters, &mut context)?;
+                let duration = start.elapsed();
+                trace.push(serde_json::json!({
+                    "action":action_request.encode(),
+                    "input_type":input_type,
+                    "output_type":value.type_identifier(),
+                    "duration_us":duration.as_micros() as u64
+                }));
+            }
+        }
+        Ok((value, serde_json::Value::Array(trace)))
+    }
+
+    /// Evaluate query and convert the result into a concrete type.
+    pub fn eval_into<R>(&self, input:T, query:&str)->Result<R,Error>
+    where R:TryFrom<T, Error=Error>
+    {
+        R::try_from(self.eval_query(input, query, &mut EvalContext::new())?)
+    }
+
+    /// Evaluate query, return the fallback value if the evaluation fails.
+    pub fn eval_or(&self, input:T, query:&str, fallback:T)->T{
+        self.eval_or_log(input, query, fallback, |_| {})
+    }
+
+    /// Evaluate query, return the fallback value if the evaluation fails; the error is passed to log.
+    pub fn eval_or_log(&self, input:T, query:&str, fallback:T, mut log:impl FnMut(&Error))->T{
+        match self.eval_query(input, query, &mut EvalContext::new()){
+            Ok(value) => value,
+            Err(e) => {
+                log(&e);
+                fallback
+            }
+        }
+    }
+}
+
+/// Single step of a compiled program: action resolved in the registry together with its parameters.
+enum ProgramStep<'a,T>{
+    /// Segment header, applied as in the evaluation (see apply_header)
+    Header(SegmentHeader),
+    Action{
+        name:String,
+        action:&'a dyn CallableAction<T>,
+        parameters:Vec<ActionParameter>
+    }
+}
+
+/// Check that the segment header is supported by the evaluation (see apply_header).
+fn check_header(header:&SegmentHeader)->Result<(),Error>{
+    match &header.name[..]{
+        "" => Ok(()),
+        "as" if header.parameters.is_empty() => Err(Error::ArgumentNotSpecified{index:0, action:Some("as".to_owned())}),
+        "as" => Ok(()),
+        name => Err(Error::ParseError{message:format!("Unsupported segment header {}", name), position:header.position.clone(), recovered_to:None})
+    }
+}
+
+/// Query compiled against a registry; parsed and with all the actions looked up once,
+/// so that it can be executed repeatedly without the parsing and registry lookups.
+pub struct Program<'a,T>{
+    registry:&'a HashMapActionRegistry<T>,
+    steps:Vec<ProgramStep<'a,T>>
+}
+
+impl<T> HashMapActionRegistry<T>{
+    /// Parse query and look up all its actions; fails if the query can't be parsed, an action is not registered,
+    /// the number of parameters doesn't match the action's expected parameter count,
+    /// a parameter can't be converted to the type declared in the action metadata (see check_parameter_types)
+    /// or a segment header is not supported (see check_header).
+    pub fn compile(&self, query:&str)->Result<Program<'_,T>,Error>{
+        let mut steps = Vec::new();
+        for segment in parse(query)?.segments{
+            if let Some(header) = segment.header{
+                check_header(&header)?;
+                steps.push(ProgramStep::Header(header));
+            }
+            for action_request in segment.query{
+                let action = self.get_callable_action("root", &action_request.name)
+                .ok_or_else(|| Error::ActionNotRegistered{message:format!("Action {} not registered in namespace root",action_request.name)})?;
+                if let Some(count) = action.expected_param_count(){
+                    if count != action_request.parameters.len(){
+                        return Err(Error::ParameterError{
+                            message:format!("Action {} expects {} parameter(s), {} given", action_request.name, count, action_request.parameters.len()),
+                            position:action_request.position
+                        });
+                    }
+                }
+                self.check_parameter_types(&action_request)?;
+                steps.push(ProgramStep::Action{name:action_request.name.to_string(), action, parameters:action_request.parameters});
+            }
+        }
+        Ok(Program{registry:self, steps})
+    }
+}
+
+impl<T> HashMapActionRegistry<T>{
+    /// Check the string parameters of the action request against the parameter types declared in the action metadata
+    /// (if any); links and variables are only known at the evaluation and are not checked.
+    /// Arguments in the error message are numbered counting the input as argument 0.
+    pub fn check_parameter_types(&self, action_request:&ActionRequest)->Result<(),Error>{
+        let declared = match self.get_action_metadata("root", &action_request.name){
+            Some(metadata) => &metadata.parameters,
+            None => return Ok(())
+        };
+        for (index, (parameter, info)) in action_request.parameters.iter().zip(declared.iter()).enumerate(){
+            if let ActionParameter::String(text, position) | ActionParameter::Named(text, position) = parameter{
+                if !info.param_type.accepts(text){
+                    return Err(Error::ParameterError{
+                        message:format!("argument {} of {} expected {}, got '{}'", index+1, action_request.name, info.param_type.name(), text),
+                        position:position.clone()
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a,T> Program<'a,T>
+where
+    T:Clone + Default + TryInto<String>,
+    <T as std::convert::TryInto<String>>::Error:Display
+    {
+    /// Execute the program on the input value.
+    /// Links in parameters are resolved on every run.
+    pub fn run(&self, input:T)->Result<T,Error>{
+        let mut context = EvalContext::new();
+        let starts_with_loader = matches!(self.steps.first(), Some(ProgramStep::Action{action, ..}) if action.is_loader());
+        let mut value = if starts_with_loader {T::default()} else {input};
+        for step in self.steps.iter(){
+            match step{
+                ProgramStep::Header(header) => self.registry.apply_header(&value, header, &mut context)?,
+                ProgramStep::Action{name, action, parameters} => {
+                    let parameters = self.registry.resolve_parameters(parameters, &mut vec![], &mut context)?;
+                    value = action.call_action_with_context(value, &parameters, &mut context)
+                    .map_err(|e| e.with_action(name))?;
+                }
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Links and variables are resolved to text, hence the evaluation requires `T:Default + TryInto<String>`;
+/// registries of value types without a text conversion can't be evaluated via `Environment`.
+impl<T> Environment<T> for HashMapActionRegistry<T>
+where
+    T:Clone + Default + TryInto<String>,
+    <T as std::convert::TryInto<String>>::Error:Display
+    {
+    fn eval(&mut self, input:T, query:&str)->Result<T,Error>{
+        self.eval_query(input, query, &mut EvalContext::new())
+    }
+    /// The hint is available to the actions as `EvalContext::input_metadata`.
+    fn eval_with_hint(&mut self, input:T, query:&str, hint:Option<Metadata>)->Result<T,Error>{
+        let mut context = EvalContext{input_metadata:hint, ..EvalContext::new()};
+        self.eval_query(input, query, &mut context)
+    }
+}
+
+
+impl<T> HashMapActionRegistry<T>
+where
+    T:ValueSerializer + Clone + Default + TryInto<String>,
+    <T as std::convert::TryInto<String>>::Error:Display
+    {
+    /// Evaluate query with the input value deserialized from input_text in the specified format.
+    pub fn eval_from_str(&mut self, input_text:&str, format:&str, query:&str)->Result<T,Error>{
+        let input = T::from_bytes(input_text.as_bytes(), format)?;
+        self.eval(input, query)
+    }
+}
+
+/// Closure receiving the input value and the raw action parameters.
+pub type ActionClosure<T> = Box<dyn Fn(T, &[ActionParameter]) -> Result<T, Error>>;
+
+/// Lightweight environment evaluating queries with plain closures.
+#[derive(Default)]
+pub struct ClosureEnvironment<T>(HashMap<String, ActionClosure<T>>);
+
+impl<T> ClosureEnvironment<T>{
+    pub fn new()->Self{
+        ClosureEnvironment::<T>(HashMap::new())
+    }
+
+    pub fn register(&mut self, name:&str, f:ActionClosure<T>){
+        self.0.insert(name.to_owned(), f);
+    }
+}
+
+impl<T> Environment<T> for ClosureEnvironment<T>{
+    fn eval(&mut self, input:T, query:&str)->Result<T,Error>{
+        let path = parse_query_simple(query)?;
+
+        let mut value = input;
+        for action_request in path{
+            let f = self.0.get(action_request.name.as_str())
+            .ok_or_else(|| Error::ActionNotRegistered{message:format!("Action {} not registered",action_request.name)})?;
+            value = f(value, &action_request.parameters)?;
+        }
+        Ok(value)
+    }
+}
+
+/// Registry overlaying new registrations over a base registry.
+/// Registrations are only kept in the overlay, the base registry is not modified
+/// and the overlay is discarded when the scoped registry is dropped.
+/// This allows to try registrations speculatively without cloning the actions.
+pub struct ScopedRegistry<'a,T>{
+    base:&'a HashMapActionRegistry<T>,
+    overlay:HashMapActionRegistry<T>,
+}
+
+impl<'a,T> ScopedRegistry<'a,T>{
+    pub fn new(base:&'a HashMapActionRegistry<T>)->Self{
+        ScopedRegistry{base, overlay:HashMapActionRegistry::new()}
+    }
+
+    pub fn register_callable_action(&mut self, ns:&str, name:&str, action:Box<dyn CallableAction<T> /*+ Send*/>){
+        self.overlay.register_callable_action(ns, name, action);
+    }
+
+    pub fn get_callable_action(&self, ns:&str, name:&str)->Option<&dyn CallableAction<T>>{
+        self.overlay.get_callable_action(ns, name)
+        .or_else(|| self.base.get_callable_action(ns, name))
+    }
+
+    /// Call action, the overlay takes precedence over the base registry.
+    pub fn call(&self, ns:&str, name:&str, input:T, arguments:&Vec<ActionParameter>)->Result<T, Error>{
+        self.get_callable_action(ns, name)
+        .ok_or_else(|| Error::ActionNotRegistered{message:format!("Action {} not registered in namespace {}",name,ns)})?
+        .call_action(input, arguments)
+        .map_err(|e| e.with_action(name))
+    }
+}
+
+impl<'a,T> Environment<T> for ScopedRegistry<'a,T>{
+    fn eval(&mut self, input:T, query:&str)->Result<T,Error>{
+        let path = parse_query_simple(query)?;
+
+        let mut value = input;
+        for action_request in path{
+            value = self.call("root", &action_request.name, value, &action_request.parameters)?
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::value::*;
+
+    #[test]
+    fn closure_call_action()-> Result<(), Box<dyn std::error::Error>>{
+        let a = |x:i32| x*x;
+        let result = a.call_action(Value::Integer(2),&vec![])?;
+        assert_eq!(result, Value::Integer(4));
+        Ok(())
+    }
+
+    #[test]
+    fn function1_call_action()-> Result<(), Box<dyn std::error::Error>>{
+        let a = |x:i32| x*x;
+        //let f:Function1<i32,i32> = Function1(Box::new(a));
+        let result = Function1(Box::new(a)).call_action(Value::Integer(2),&vec![])?;
+        assert_eq!(result, Value::Integer(4));
+        Ok(())
+    }
+    #[test]
+    fn function2_call_action()-> Result<(), Box<dyn std::error::Error>>{
+        let a = |x:i32,y:i32| x*y;
+        //let f:Function1<i32,i32> = Function1(Box::new(a));
+        let result = Function2(Box::new(a)).call_action(Value::Integer(2),&vec![ActionParameter::new("3")])?;
+        assert_eq!(result, Value::Integer(6));
+        Ok(())
+    }
+    /*
+    #[test]
+    fn test3()-> Result<(), Box<dyn std::error::Error>>{
+        let a = |x:i32| x*x;
+        let f:Function1<i32,i32> = Function1(Box::new(a));
+        assert_eq!(call1(f,Value::Integer(2))?, Value::Integer(4));
+        Ok(())
+    }
+    */
+    #[test]
+    fn test_registry1()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let a = |x:i32| x*x;
+        registry.register_callable_action("root", "test", Box::new(Function1(Box::new(a))));
+        let result = registry.call("root", "test", Value::Integer(2), &vec![])?;
+        assert_eq!(result, Value::Integer(4));
+        Ok(())   
+    }
+    #[test]
+    fn test_eval()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        let result = registry.eval(Value::Integer(2),"square/add-10")?;
+        assert_eq!(result, Value::Integer(14));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_hello()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let hello = |x:String| format!("Hello, {}!",x);
+        registry.register_callable_action("root", "hello", Box::new(Function1(Box::new(hello))));
+        let result = registry.eval(Value::Text("world".to_owned()),"hello")?;
+        assert_eq!(result, Value::Text("Hello, world!".to_owned()));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_eval_from_str()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        let result = registry.eval_from_str(r#"{"Integer":3}"#, "json", "square")?;
+        assert_eq!(result, Value::Integer(9));
+        assert!(registry.eval_from_str("3x", "json", "square").is_err());
+        Ok(())   
+    }
+
+    #[test]
+    fn test_multiple_outputs()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let divmod = |_x:Value, a:i32, b:i32| vec![Value::Integer(a/b), Value::Integer(a%b)];
+        registry.register_callable_action("root", "divmod", Box::new(Function3(Box::new(divmod))));
+        registry.register_callable_action("root", "item", Box::new(ItemAction));
+        let result = registry.eval(Value::None,"divmod-7-3")?;
+        assert_eq!(result, Value::List(vec![Value::Integer(2), Value::Integer(1)]));
+        let result = registry.eval(Value::None,"divmod-7-3/item-1")?;
+        assert_eq!(result, Value::Integer(1));
+        assert!(registry.eval(Value::None,"divmod-7-3/item-2").is_err());
+        Ok(())   
+    }
+
+    #[test]
+    fn test_scoped_registry()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        let cube = |x:i32| x*x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        {
+            let mut scoped = ScopedRegistry::new(&registry);
+            scoped.register_callable_action("root", "square", Box::new(Function1(Box::new(cube))));
+            scoped.register_callable_action("root", "cube", Box::new(Function1(Box::new(cube))));
+            assert_eq!(scoped.eval(Value::Integer(2),"square")?, Value::Integer(8));
+            assert_eq!(scoped.eval(Value::Integer(2),"cube")?, Value::Integer(8));
+        }
+        assert_eq!(registry.eval(Value::Integer(2),"square")?, Value::Integer(4));
+        assert!(registry.eval(Value::Integer(2),"cube").is_err());
+        Ok(())   
+    }
+
+    #[test]
+    fn test_resolve_links()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let seven = |_x:Value| 7;
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "seven", Box::new(Function1(Box::new(seven))));
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        let parameters = vec![ActionParameter::Link("seven".to_owned(), Position::unknown())];
+        let resolved = registry.resolve_parameters(&parameters, &mut vec![], &mut EvalContext::new())?;
+        assert_eq!(registry.call("root", "add", Value::Integer(1), &resolved)?, Value::Integer(8));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_cyclic_link()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        let parameters = vec![ActionParameter::Link("add-1".to_owned(), Position::unknown())];
+        // There is no indirection resolving to a query yet, so a cycle can't be reached from eval;
+        // the query containing the link is simulated by the stack.
+        let mut stack = vec!["add-1".to_owned()];
+        match registry.resolve_parameters(&parameters, &mut stack, &mut EvalContext::new()){
+            Err(Error::General{message}) => assert!(message.starts_with("cyclic query reference")),
+            x => panic!("Cyclic reference error expected, got {:?}", x)
+        }
+        assert_eq!(stack.len(), 1);
+        // The same link resolved repeatedly (also nested) is not a cycle
+        let seven = |_x:Value| 7;
+        registry.register_callable_action("root", "seven", Box::new(Function1(Box::new(seven))));
+        assert_eq!(registry.eval(Value::Integer(1), "add-~X~seven~E/add-~X~seven~E")?, Value::Integer(15));
+        assert_eq!(registry.eval(Value::Integer(1), "add-~X~seven/add-~X~seven~E~E")?, Value::Integer(15));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_register_names()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action_names("root", &["add", "plus", "sum"], Box::new(Function2(Box::new(add))));
+        assert_eq!(registry.eval(Value::Integer(2),"add-1")?, Value::Integer(3));
+        assert_eq!(registry.eval(Value::Integer(2),"plus-2")?, Value::Integer(4));
+        assert_eq!(registry.eval(Value::Integer(2),"sum-3/add-1")?, Value::Integer(6));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_closure_environment()->Result<(),Box<dyn std::error::Error>>{
+        let mut env = ClosureEnvironment::<i32>::new();
+        env.register("square", Box::new(|x, _| Ok(x*x)));
+        env.register("add", Box::new(|x, parameters| {
+            let mut par = ActionParametersSlice::new(parameters);
+            let y:i32 = par.try_parameters_into(&mut ())?;
+            Ok(x+y)
+        }));
+        assert_eq!(env.eval(3, "square/add-1")?, 10);
+        assert!(env.eval(3, "cube").is_err());
+        Ok(())   
+    }
+
+    #[test]
+    fn test_type_of()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_callable_action("root", "type_of", type_of_action());
+        assert_eq!(registry.eval(Value::Integer(2),"type_of")?, Value::from("int"));
+        assert_eq!(registry.eval(Value::from("x"),"type_of")?, Value::from("text"));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_input_type_mismatch()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        match registry.eval(Value::from("abc"),"square"){
+            Err(Error::ConversionError{message}) => assert!(message.contains("expected int input, got text"), "{}", message),
+            x => panic!("Conversion error expected, got {:?}", x)
+        }
+        Ok(())   
+    }
+
+    #[test]
+    fn test_eval_context()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_callable_action("root", "cwd", Box::new(ContextAction(Box::new(
+            |_input:Value, _arguments:&Vec<ActionParameter>, context:&mut EvalContext|
+            Ok(Value::from(context.cwd.as_ref().map(|x| x.to_string_lossy().to_string()).unwrap_or_default()))
+        ))));
+        let mut context = EvalContext::new();
+        context.cwd = Some(PathBuf::from("data"));
+        assert_eq!(registry.eval_query(Value::None, "cwd", &mut context)?, Value::from("data"));
+        assert_eq!(registry.eval(Value::None, "cwd")?, Value::from(""));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_context_variables()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_callable_action("root", "set", Box::new(ContextAction(Box::new(
+            |input:Value, arguments:&Vec<ActionParameter>, context:&mut EvalContext|{
+                if arguments.len() != 2{
+                    return Err(Error::ArgumentNotSpecified{index:arguments.len(), action:None});
+                }
+                context.variables.insert(arguments[0].to_string(), Value::from(arguments[1].to_string()));
+                Ok(input)
+            }
+        ))));
+        registry.register_callable_action("root", "get", Box::new(ContextAction(Box::new(
+            |_input:Value, arguments:&Vec<ActionParameter>, context:&mut EvalContext|
+            Ok(arguments.first().and_then(|name| context.variables.get(&name.to_string())).cloned().unwrap_or_default())
+        ))));
+        let mut context = EvalContext::new();
+        assert_eq!(registry.eval_query(Value::None, "set-name-value/get-name", &mut context)?, Value::from("value"));
+        assert_eq!(context.variables.get("name"), Some(&Value::from("value")));
+        assert_eq!(registry.eval_query(Value::None, "get-name", &mut context)?, Value::from("value"));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_eval_into()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        assert_eq!(registry.eval_into::<i32>(Value::Integer(2), "square")?, 4);
+        assert!(registry.eval_into::<Vec<Value>>(Value::Integer(2), "square").is_err());
+        Ok(())   
+    }
+
+    #[test]
+    fn test_serialize_action()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_callable_action("root", "to_bytes", serialize_action());
+        registry.register_callable_action("root", "type_of", type_of_action());
+        let mut object = BTreeMap::new();
+        object.insert("a".to_owned(), Value::Integer(1));
+        let input = Value::Object(object);
+        let expected = input.as_bytes("json")?;
+        assert_eq!(registry.eval(input.clone(), "to_bytes-json")?, Value::Bytes(expected));
+        assert_eq!(registry.eval(input.clone(), "to_bytes-json/type_of")?, Value::from("bytes"));
+        assert!(registry.eval(input, "to_bytes").is_err());
+        Ok(())   
+    }
+
+    #[test]
+    fn test_find()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        for name in ["plot_line", "plot_bar", "plot", "replot_bar", "square"].iter(){
+            registry.register_callable_action("root", name, type_of_action());
+        }
+        assert_eq!(registry.find("root", "plot_*"), vec!["plot_bar", "plot_line"]);
+        assert_eq!(registry.find("root", "*plot*"), vec!["plot", "plot_bar", "plot_line", "replot_bar"]);
+        assert_eq!(registry.find("root", "*_b*r"), vec!["plot_bar", "replot_bar"]);
+        assert_eq!(registry.find("root", "square"), vec!["square"]);
+        assert!(registry.find("other", "*").is_empty());
+        assert!(!glob_match("a*a", "a"));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_loader()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_loader("root", "load", Box::new(ContextAction(Box::new(
+            |input:Value, arguments:&Vec<ActionParameter>, _context:&mut EvalContext|{
+                assert_eq!(input, Value::None);
+                match (arguments.first().map(|x| x.to_string()), arguments.get(1)){
+                    (Some(kind), Some(x)) if kind == "constant" => Ok(Value::from(x.to_string())),
+                    _ => Err(Error::General{message:"Unknown resource".to_owned()})
+                }
+            }
+        ))));
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        registry.register_callable_action("root", "int", Box::new(ContextAction(Box::new(
+            |input:Value, _arguments:&Vec<ActionParameter>, _context:&mut EvalContext|
+            input.as_text().and_then(|x| x.parse::<i32>().ok()).map(Value::Integer)
+            .ok_or_else(|| Error::ConversionError{message:"Integer expected".to_owned()})
+        ))));
+        assert_eq!(registry.eval(Value::from("ignored"), "load-constant-42/int/add-1")?, Value::Integer(43));
+        assert_eq!(registry.compile("load-constant-42/int")?.run(Value::Integer(1))?, Value::Integer(42));
+        assert!(registry.eval(Value::None, "load-x-1").is_err());
+        Ok(())   
+    }
+
+    #[test]
+    fn test_named_results()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        let mut context = EvalContext::new();
+        assert_eq!(registry.eval_query(Value::Integer(3), "square/-as-x/add-1/add-~V~x~E", &mut context)?, Value::Integer(19));
+        assert_eq!(context.variables.get("x"), Some(&Value::from("9")));
+        assert_eq!(registry.eval(Value::Integer(3), "-as-x/square/-/add-~V~x~E")?, Value::Integer(12));
+        match registry.eval(Value::Integer(3), "add-~V~y~E"){
+            Err(Error::ParameterError{message, ..}) => assert!(message.contains("y")),
+            x => panic!("ParameterError expected, got {:?}", x)
+        }
+        assert!(registry.eval(Value::Integer(3), "-xyz/square").is_err());
+        Ok(())   
+    }
+
+    #[test]
+    fn test_output_media_types()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_callable_action("root", "to_bytes", serialize_action());
+        registry.register_action_metadata("root", "to_bytes", ActionMetadata{output_media_types:vec!["application/octet-stream".to_owned()], ..ActionMetadata::default()});
+        assert_eq!(registry.output_media_types(&parse("to_bytes-json/out.json")?), vec!["application/json"]);
+        assert_eq!(registry.output_media_types(&parse("to_bytes-json/out.serde.json")?), vec!["application/json"]);
+        assert_eq!(registry.output_media_types(&parse("to_bytes-json")?), vec!["application/octet-stream"]);
+        assert!(registry.output_media_types(&parse("square")?).is_empty());
+        registry.unregister_callable_action("root", "to_bytes");
+        assert!(registry.get_action_metadata("root", "to_bytes").is_none());
+        Ok(())   
+    }
+
+    #[test]
+    fn test_metadata_hint()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_callable_action("root", "media_type", Box::new(ContextAction(Box::new(
+            |_input:Value, _arguments:&Vec<ActionParameter>, context:&mut EvalContext|
+            Ok(Value::from(context.input_metadata.as_ref().map(|x| x.media_type.to_owned()).unwrap_or_default()))
+        ))));
+        let hint = Metadata{type_identifier:"bytes".to_owned(), format:"png".to_owned(), media_type:"image/png".to_owned()};
+        let (value, metadata) = registry.eval_with_metadata_hint(Value::Bytes(vec![0x89, 0x50]), "media_type", Some(hint))?;
+        assert_eq!(value, Value::from("image/png"));
+        assert_eq!(metadata.type_identifier, "text");
+        let (value, _) = registry.eval_with_metadata(Value::Bytes(vec![0x89, 0x50]), "media_type")?;
+        assert_eq!(value, Value::from(""));
+        Ok(())   
+    }
+
+    #[test]
+    fn test_eval_or()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        assert_eq!(registry.eval_or(Value::Integer(2), "square", Value::from("n/a")), Value::Integer(4));
+        let mut errors = vec![];
+        let result = registry.eval_or_log(Value::Integer(2), "square/cube", Value::from("n/a"), |e| errors.push(e.clone()));
+        assert_eq!(result, Value::from("n/a"));
+        assert_eq!(errors.len(), 1);
+        match &errors[0]{
+            Error::ActionNotRegistered{message} => assert!(message.contains("cube")),
+            x => panic!("ActionNotRegistered expected, got {:?}", x)
+        }
+        Ok(())   
+    }
+
+    #[test]
+    fn test_argument_not_specified()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let add = |x:i32,y:i32| x+y;
+        let divmod = |_x:Value, a:i32, b:i32| vec![Value::Integer(a/b), Value::Integer(a%b)];
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        registry.register_callable_action("root", "divmod", Box::new(Function3(Box::new(divmod))));
+        for (query, index, action) in [("add", 1, "add"), ("divmod-7", 2, "divmod")].iter(){
+            match registry.eval(Value::Integer(1), query){
+                Err(Error::ArgumentNotSpecified{index:i, action:a}) => {
+                    assert_eq!(i, *index);
+                    assert_eq!(a, Some(action.to_string()));
+                },
+                x => panic!("ArgumentNotSpecified expected, got {:?}", x)
+            }
+        }
+        let scoped = ScopedRegistry::new(&registry);
+        match scoped.call("root", "add", Value::Integer(1), &vec![]){
+            Err(Error::ArgumentNotSpecified{index, action}) => {
+                assert_eq!(index, 1);
+                assert_eq!(action, Some("add".to_owned()));
+            },
+            x => panic!("ArgumentNotSpecified expected, got {:?}", x)
+        }
+        Ok(())   
+    }
+
+    #[test]
+    fn test_compile_program() -> Result<(), Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        let program = registry.compile("square/add-10")?;
+        assert_eq!(program.run(Value::Integer(2))?, Value::Integer(14));
+        assert_eq!(program.run(Value::Integer(3))?, Value::Integer(19));
+        assert!(registry.compile("square/cube").is_err());
+        assert!(registry.compile("add-1").is_ok());
+        match registry.compile("square/add-1-2"){
+            Err(Error::ParameterError{message, position}) => {
+                assert!(message.contains("add"));
+                assert_eq!(position.offset, 7);
+            },
+            Err(e) => panic!("ParameterError expected, got {:?}", e),
+            Ok(_) => panic!("ParameterError expected")
+        }
+        assert!(registry.compile("square-1").is_err());
+        let program = registry.compile("square/-as-x/add-~V~x~E")?;
+        assert_eq!(program.run(Value::Integer(3))?, registry.eval_query(Value::Integer(3), "square/-as-x/add-~V~x~E", &mut EvalContext::new())?);
+        assert_eq!(program.run(Value::Integer(3))?, Value::Integer(18));
+        assert!(registry.compile("square/-xyz/add-1").is_err());
+        assert!(registry.compile("square/-as/add-1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_observer() -> Result<(), Box<dyn std::error::Error>>{
+        let events = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let captured = events.clone();
+        registry.on_change(Box::new(move |event| captured.borrow_mut().push(event.clone())));
+        let square = |x:i32| x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        assert!(registry.unregister_callable_action("root", "square").is_some());
+        assert!(registry.unregister_callable_action("root", "square").is_none());
+        assert_eq!(*events.borrow(), vec![
+            RegistryEvent::Registered{ns:"root".to_owned(), name:"square".to_owned()},
+            RegistryEvent::Unregistered{ns:"root".to_owned(), name:"square".to_owned()},
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry_from_specs() -> Result<(), Box<dyn std::error::Error>>{
+        let mut builtins = BuiltinTable::<Value>::new();
+        let square = |x:i32| x*x;
+        let add = |x:i32,y:i32| x+y;
+        builtins.register("square", Box::new(Function1(Box::new(square))));
+        builtins.register("add", Box::new(Function2(Box::new(add))));
+        let specs:Vec<ActionSpec> = serde_json::from_str(r#"[
+            {"name":"sq", "op":"square"},
+            {"name":"inc", "op":"add", "parameters":["1"]}
+        ]"#)?;
+        let mut registry = HashMapActionRegistry::from_specs(&specs, &builtins)?;
+        assert_eq!(registry.eval(Value::Integer(3), "sq/inc")?, Value::Integer(10));
+        assert_eq!(registry.eval(Value::Integer(3), "inc-5")?, Value::Integer(8));
+        let unknown = vec![ActionSpec{name:"x".to_owned(), op:"cube".to_owned(), parameters:vec![]}];
+        assert!(HashMapActionRegistry::from_specs(&unknown, &builtins).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_with_metadata() -> Result<(), Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        let (value, metadata) = registry.eval_with_metadata(Value::Integer(2), "square/out.csv")?;
+        assert_eq!(value, Value::Integer(4));
+        assert_eq!(metadata.type_identifier, "int");
+        assert_eq!(metadata.format, "csv");
+        assert_eq!(metadata.media_type, "text/csv");
+        let (_, metadata) = registry.eval_with_metadata(Value::Integer(2), "square")?;
+        assert_eq!(metadata.format, "json");
+        assert_eq!(metadata.media_type, "application/json");
+        let (_, metadata) = registry.eval_with_metadata(Value::Integer(2), "square/x-y.csv")?;
+        assert_eq!(metadata.format, "csv");
+        let (_, metadata) = registry.eval_with_metadata(Value::Integer(2), "square/out.serde.json")?;
+        assert_eq!(metadata.format, "serde.json");
+        assert_eq!(metadata.media_type, "application/json");
+        // Only whole extensions select the format
+        assert!(registry.eval_with_metadata(Value::Integer(2), "square/x.ab").is_err());
+        Ok(())
+    }
+
+    /// Minimal custom value type
+    #[derive(Debug, Clone, PartialEq, Default)]
+    enum MyValue{
+        #[default]
+        Nothing,
+        Number(i32),
+        Label(String),
+    }
+
+    impl ValueSerializer for MyValue{
+        type Formats = crate::formats::ValueSerializationFormats;
+        fn type_identifier(&self)->String{
+            match self{
+                MyValue::Nothing => "nothing".to_owned(),
+                MyValue::Number(_) => "number".to_owned(),
+                MyValue::Label(_) => "label".to_owned(),
+            }
+        }
+        fn default_extension(&self)->String{
+            "txt".to_owned()
+        }
+        fn default_media_type(&self)->String{
+            "text/plain".to_owned()
+        }
+        fn as_bytes(&self, _format:&str)->Result<Vec<u8>, Error>{
+            String::try_from(self.clone()).map(|x| x.into_bytes())
+            .map_err(|message| Error::SerializationError{message, format:"txt".to_owned()})
+        }
+        fn from_bytes(b: &[u8], _format:&str)->Result<Self, Error>{
+            Ok(MyValue::Label(String::from_utf8_lossy(b).to_string()))
+        }
+    }
+
+    impl TryFrom<MyValue> for i32{
+        type Error = String;
+        fn try_from(value:MyValue)->Result<i32, String>{
+            match value{
+                MyValue::Number(x) => Ok(x),
+                x => Err(format!("{:?} is not a number", x))
+            }
+        }
+    }
+
+    impl TryFrom<MyValue> for String{
+        type Error = String;
+        fn try_from(value:MyValue)->Result<String, String>{
+            match value{
+                MyValue::Nothing => Ok(String::new()),
+                MyValue::Number(x) => Ok(x.to_string()),
+                MyValue::Label(x) => Ok(x),
+            }
+        }
+    }
+
+    impl From<i32> for MyValue{
+        fn from(x:i32)->MyValue{
+            MyValue::Number(x)
+        }
+    }
+
+    fn value_like<T:ValueLike>(_value:&T){}
+
+    #[test]
+    fn custom_value_type()->Result<(),Box<dyn std::error::Error>>{
+        value_like(&MyValue::Nothing);
+        let mut registry = HashMapActionRegistry::<MyValue>::new();
+        let square = |x:i32| x*x;
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        assert_eq!(registry.eval(MyValue::Number(3), "square/add-1")?, MyValue::Number(10));
+        assert_eq!(registry.eval(MyValue::Number(3), "square/-as-x/add-~V~x~E")?, MyValue::Number(18));
+        match registry.eval(MyValue::Label("x".to_owned()), "square"){
+            Err(Error::ConversionError{message}) => assert!(message.contains("label")),
+            x => panic!("ConversionError expected, got {:?}", x)
+        }
+        Ok(())   
+    }
+
+    #[test]
+    fn test_eval_stream()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_callable_action("root", "range", Box::new(ChunkedAction(Box::new(
+            |input:Value, _arguments:&Vec<ActionParameter>|{
+                let n = input.as_integer().ok_or_else(|| Error::ConversionError{message:"Integer expected".to_owned()})?;
+                Ok(Box::new((0..n).map(|i| Ok(Value::Integer(i)))) as ValueStream<'static,Value>)
+            }
+        ))));
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+
+        let mut stream = registry.eval_stream(Value::Integer(1_000_000), "add-0/range", &mut EvalContext::new())?;
+        assert_eq!(stream.next().transpose()?, Some(Value::Integer(0)));
+        assert_eq!(stream.nth(9).transpose()?, Some(Value::Integer(10)));
+        let partial:Vec<Value> = registry.eval_stream(Value::Integer(10), "add-5/range", &mut EvalContext::new())?
+        .take(3).collect::<Result<_,_>>()?;
+        assert_eq!(partial, vec![Value::Integer(0), Value::Integer(1), Value::Integer(2)]);
+
+        assert_eq!(registry.eval_query(Value::Integer(2), "range", &mut EvalContext::new())?, Value::from(vec![Value::Integer(0), Value::Integer(1)]));
+        assert!(registry.eval_stream(Value::Integer(2), "range/add-1", &mut EvalContext::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        registry.register_action_metadata("root", "square", ActionMetadata{doc:"Square of a number".to_owned(), ..ActionMetadata::default()});
+        let plan = registry.explain("square/add-10")?;
+        assert_eq!(plan.lines().count(), 2);
+        assert_eq!(plan, "square [root] () - Square of a number\nadd [root] (10)");
+        let plan = registry.explain("add/cube")?;
+        assert_eq!(plan, "add [root] () !! expects 1 parameter(s), 0 given\ncube [root] () !! not registered");
+        assert!(registry.explain("add-'x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_traced()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        registry.register_callable_action("root", "type", type_of_action());
+        let (value, trace) = registry.eval_traced(Value::Integer(3), "square/-/add-10/type")?;
+        assert_eq!(value, Value::from("int"));
+        let trace = trace.as_array().unwrap();
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace[1]["action"], "add-10");
+        assert_eq!(trace[1]["input_type"], "int");
+        assert_eq!(trace[2]["output_type"], "text");
+        assert!(trace[0]["duration_us"].is_u64());
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_action()->Result<(),Box<dyn std::error::Error>>{
+        let upper = ContextAction(Box::new(
+            |input:MyValue, arguments:&Vec<ActionParameter>, _context:&mut EvalContext|{
+                let suffix:String = arguments.iter().map(|x| x.to_string()).collect();
+                Ok(MyValue::Label(String::try_from(input).map_err(|message| Error::ConversionError{message})?.to_uppercase() + &suffix))
+            }
+        ));
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_callable_action("root", "upper", Box::new(ConvertAction::<Value,MyValue>::new(Box::new(upper), "txt")));
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        assert_eq!(registry.eval(Value::from("abc"), "upper-x")?, Value::from("ABCx"));
+        assert_eq!(registry.eval(Value::Integer(1), "add-2/upper")?, Value::from("3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_any()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        let add = |x:i32,y:i32| x+y;
+        registry.register_callable_action("plugin", "square", Box::new(Function1(Box::new(square))));
+        registry.register_callable_action("root", "add", Box::new(Function2(Box::new(add))));
+        registry.register_callable_action("other", "add", Box::new(Function2(Box::new(add))));
+        assert_eq!(registry.call_any("square", Value::Integer(3), &vec![])?, Value::Integer(9));
+        match registry.call_any("add", Value::Integer(3), &vec![ActionParameter::new("1")]){
+            Err(Error::General{message}) => assert!(message.contains("other, root")),
+            x => panic!("General error expected, got {:?}", x)
+        }
+        assert!(matches!(registry.call_any("cube", Value::Integer(3), &vec![]), Err(Error::ActionNotRegistered{..})));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parameter_types()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let scale = |x:i32, offset:i32, factor:f64| (x + offset) as f64 * factor;
+        registry.register_callable_action("root", "scale", Box::new(Function3(Box::new(scale))));
+        registry.register_action_metadata("root", "scale", ActionMetadata{
+            parameters:vec![
+                ParamInfo{name:"offset".to_owned(), param_type:ParamType::Int},
+                ParamInfo{name:"factor".to_owned(), param_type:ParamType::Real},
+            ],
+            ..ActionMetadata::default()
+        });
+        assert_eq!(registry.compile("scale-1-2")?.run(Value::Integer(3))?, Value::Real(8.0));
+        match registry.compile("scale-1-abc"){
+            Err(Error::ParameterError{message, position}) => {
+                // The input is argument 0, as in ArgumentNotSpecified
+                assert_eq!(message, "argument 2 of scale expected real, got 'abc'");
+                assert_eq!(position.offset, 8);
+            },
+            _ => panic!("ParameterError expected")
+        }
+        assert!(registry.compile("scale-1-~X~x~E").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_action_metadata_defaults()->Result<(),Box<dyn std::error::Error>>{
+        let metadata:ActionMetadata = serde_json::from_str("{}")?;
+        assert_eq!(metadata, ActionMetadata::default());
+        let metadata:ActionMetadata = serde_json::from_str(r#"{"doc":"Square of the input"}"#)?;
+        assert!(metadata.output_media_types.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_to_bytes()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        let (bytes, metadata) = registry.eval_to_bytes(Value::Integer(3), "square/out.json", "txt")?;
+        assert_eq!(bytes, b"9");
+        assert_eq!(metadata.media_type, "text/plain");
+        Ok(())
+    }
+
+    #[test]
+    fn test_literal_action()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        registry.register_callable_action("root", "value", literal_action());
+        assert_eq!(registry.eval(Value::from("ignored"), "value-42/square")?, Value::Integer(1764));
+        assert_eq!(registry.eval(Value::None, "value-'1.5'")?, Value::Real(1.5));
+        assert_eq!(registry.eval(Value::None, "value-true")?, Value::Bool(true));
+        assert_eq!(registry.eval(Value::None, "value-abc")?, Value::from("abc"));
+        Ok(())
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Celsius(f64);
+    crate::impl_parameter_newtype!(Celsius, f64);
+
+    #[test]
+    fn test_parameter_newtype()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let warm = |x:Celsius, by:Celsius| Celsius(x.0 + by.0);
+        registry.register_callable_action("root", "warm", Box::new(Function2(Box::new(warm))));
+        assert_eq!(registry.eval(Value::Real(20.0), "warm-5")?, Value::Real(25.0));
+        assert!(registry.eval(Value::Real(20.0), "warm-x").is_err());
+        assert_eq!(Celsius::try_from(Value::Integer(3))?, Celsius(3.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_infer_output_type()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        let square = |x:i32| x*x;
+        registry.register_callable_action("root", "square", Box::new(Function1(Box::new(square))));
+        registry.register_callable_action("root", "type", type_of_action());
+        registry.register_callable_action("root", "to_bytes", serialize_action());
+        registry.register_output_type("root", "square", "int");
+        registry.register_output_type("root", "type", "text");
+        assert_eq!(registry.infer_output_type(&parse("square")?), Some("int".to_owned()));
+        assert_eq!(registry.infer_output_type(&parse("square/-/square/type")?), Some("text".to_owned()));
+        assert_eq!(registry.infer_output_type(&parse("square/to_bytes-json")?), None);
+        assert_eq!(registry.infer_output_type(&parse("")?), None);
+        registry.register_callable_action("root", "head", head_action());
+        registry.register_output_type("root", "head", PASS_THROUGH_TYPE);
+        registry.register_input_type("root", "square", "int");
+        assert_eq!(registry.infer_output_type(&parse("type/head-2")?), Some("text".to_owned()));
+        assert_eq!(registry.infer_output_type(&parse("to_bytes-json/head-2")?), None);
+        // Mismatch of the declared types
+        assert_eq!(registry.infer_output_type(&parse("type/square")?), None);
+        assert_eq!(registry.infer_output_type(&parse("square/head-2/square")?), Some("int".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_head_tail()->Result<(),Box<dyn std::error::Error>>{
+        let mut registry = HashMapActionRegistry::<Value>::new();
+        registry.register_callable_action("root", "head", head_action());
+        registry.register_callable_action("root", "tail", tail_action());
+        let list = Value::from(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]);
+        assert_eq!(registry.eval(list.clone(), "head")?, Value::Integer(1));
+        assert_eq!(registry.eval(list.clone(), "tail")?, Value::from(vec![Value::Integer(2), Value::Integer(3)]));
+        assert_eq!(registry.eval(list, "tail/tail/head")?, Value::Integer(3));
+        assert!(registry.eval(Value::List(vec![]), "head").is_err());
+        assert!(registry.eval(Value::List(vec![]), "tail").is_err());
+        assert!(matches!(registry.eval(Value::Integer(1), "head"), Err(Error::ConversionError{..})));
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -4,28 +4,64 @@ use crate::query::Position;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Error{
-    ArgumentNotSpecified,
+    /// Missing argument of an action; for the function actions (Function2, Function3)
+    /// the input is argument 0, so the first parameter is argument 1.
+    ArgumentNotSpecified{index:usize, action:Option<String>},
     ActionNotRegistered{message:String},
-    ParseError{message:String, position:Position},
+    /// Query can't be parsed; recovered_to is the position up to which the query is valid (if known),
+    /// i.e. the start of the first element (action, header or filename) which can't be parsed.
+    ParseError{message:String, position:Position, recovered_to:Option<Position>},
     ParameterError{message:String, position:Position},
+    LimitExceeded{message:String, position:Position},
     ConversionError{message:String},
     SerializationError{message:String, format:String},
+    Timeout{message:String},
+    Cancelled{message:String},
     General{message:String}
 }
 
 impl fmt::Display for Error{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::ArgumentNotSpecified => write!(f, "Argument not specified"),
+            Error::ArgumentNotSpecified{index, action:None} => write!(f, "Argument {} not specified", index),
+            Error::ArgumentNotSpecified{index, action:Some(action)} => write!(f, "Argument {} of {} not specified", index, action),
             Error::ActionNotRegistered{message} => write!(f, "Error: {}", message),
-            Error::ParseError{message, position} => write!(f, "Error: {} {}", message, position),
+            Error::ParseError{message, position, ..} => write!(f, "Error: {} {}", message, position),
             Error::ParameterError{message, position} => write!(f, "Error: {} {}", message, position),
+            Error::LimitExceeded{message, position} => write!(f, "Error: {} {}", message, position),
             Error::ConversionError{message} => write!(f, "Error: {}", message),
             Error::SerializationError{message, format:_} => write!(f, "Error: {}", message),
+            Error::Timeout{message} => write!(f, "Timeout: {}", message),
+            Error::Cancelled{message} => write!(f, "Cancelled: {}", message),
             Error::General{message} => write!(f, "Error: {}", message),
         }
     }    
 }
+impl Error{
+    /// Add the action name to the error (if the error supports it and the action is not yet known).
+    pub fn with_action(self, action_name:&str)->Self{
+        match self{
+            Error::ArgumentNotSpecified{index, action:None} => Error::ArgumentNotSpecified{index, action:Some(action_name.to_owned())},
+            e => e
+        }
+    }
+    /// HTTP status code suitable for reporting the error from a web server.
+    pub fn http_status(&self)->u16{
+        match self{
+            Error::ArgumentNotSpecified{..} => 400,
+            Error::ParseError{..} => 400,
+            Error::ParameterError{..} => 400,
+            Error::ConversionError{..} => 400,
+            Error::ActionNotRegistered{..} => 404,
+            Error::LimitExceeded{..} => 413,
+            Error::Cancelled{..} => 499,
+            Error::Timeout{..} => 504,
+            Error::SerializationError{..} => 500,
+            Error::General{..} => 500,
+        }
+    }
+}
+
 impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
@@ -33,3 +69,85 @@ impl error::Error for Error {
         }
     }
 }
+
+/// Multiple errors, e.g. collected while processing multiple items.
+/// Each error is stored together with the (zero-based) index of the item it belongs to.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Errors(pub Vec<(usize, Error)>);
+
+impl Errors{
+    pub fn new()->Self{
+        Errors(Vec::new())
+    }
+    /// Add an error of the next item, i.e. with the index equal to the number of errors so far.
+    pub fn push(&mut self, error:Error){
+        self.0.push((self.0.len(), error));
+    }
+    /// Add an error of the item with the given index.
+    pub fn push_item(&mut self, index:usize, error:Error){
+        self.0.push((index, error));
+    }
+    pub fn len(&self)->usize{
+        self.0.len()
+    }
+    pub fn is_empty(&self)->bool{
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<Error>> for Errors{
+    fn from(errors:Vec<Error>)->Self{
+        Errors(errors.into_iter().enumerate().collect())
+    }
+}
+
+/// Numbered list of the errors; the number is the index of the item plus one.
+impl fmt::Display for Errors{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (index, e)) in self.0.iter().enumerate(){
+            if i > 0{
+                writeln!(f)?;
+            }
+            write!(f, "{}. {}", index+1, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Errors {}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn http_status(){
+        let message = "x".to_owned();
+        let position = Position::unknown();
+        assert_eq!(Error::ArgumentNotSpecified{index:0, action:None}.http_status(), 400);
+        assert_eq!(Error::ParseError{message:message.clone(), position:position.clone(), recovered_to:None}.http_status(), 400);
+        assert_eq!(Error::ParameterError{message:message.clone(), position:position.clone()}.http_status(), 400);
+        assert_eq!(Error::ConversionError{message:message.clone()}.http_status(), 400);
+        assert_eq!(Error::ActionNotRegistered{message:message.clone()}.http_status(), 404);
+        assert_eq!(Error::LimitExceeded{message:message.clone(), position}.http_status(), 413);
+        assert_eq!(Error::Cancelled{message:message.clone()}.http_status(), 499);
+        assert_eq!(Error::Timeout{message:message.clone()}.http_status(), 504);
+        assert_eq!(Error::SerializationError{message:message.clone(), format:"json".to_owned()}.http_status(), 500);
+        assert_eq!(Error::General{message}.http_status(), 500);
+    }
+
+    #[test]
+    fn errors_display(){
+        let mut errors = Errors::new();
+        errors.push(Error::General{message:"first".to_owned()});
+        errors.push(Error::ArgumentNotSpecified{index:1, action:Some("add".to_owned())});
+        errors.push(Error::Timeout{message:"third".to_owned()});
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors.to_string(), "1. Error: first\n2. Argument 1 of add not specified\n3. Timeout: third");
+        let boxed:Box<dyn error::Error> = Box::new(errors);
+        assert!(boxed.to_string().starts_with("1. "));
+        let mut errors = Errors::new();
+        errors.push_item(4, Error::General{message:"fifth".to_owned()});
+        assert_eq!(errors.to_string(), "5. Error: fifth");
+    }
+}
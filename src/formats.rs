@@ -1,5 +1,6 @@
 use std::result::Result;
 use crate::error::Error;
+use crate::query::Position;
 
 use strum::IntoEnumIterator;
 use strum_macros::*;
@@ -9,7 +10,10 @@ use strum_macros::*;
 pub enum ValueSerializationFormats{
     Text,
     Json,
-    SerdeJson
+    SerdeJson,
+    Binary,
+    Csv,
+    Tsv
 }
 
 pub fn media_type_from_extension(extension:&str)->&'static str{
@@ -75,7 +79,7 @@ pub trait SerializationFormats where Self:Sized + IntoEnumIterator + std::fmt::D
     fn extension_from_filename(filename:&str)->Option<&'static str>{
         Self::supported_extensions().iter()
         .enumerate()
-        .filter(|(i,x)| filename.ends_with(*x))
+        .filter(|(_,x)| filename == **x || filename.ends_with(&format!(".{}", x)))
         .map(|(i,x)| (x.len(),i))
         .max()
         .map(|(_,i)| Self::supported_extensions()[i])
@@ -87,23 +91,163 @@ pub trait SerializationFormats where Self:Sized + IntoEnumIterator + std::fmt::D
 
 impl SerializationFormats for ValueSerializationFormats{
     fn supported_extensions()->&'static [&'static str]{
-        &["txt", "json", "serde.json"]
+        &["txt", "json", "serde.json", "b", "bytes", "raw", "csv", "tsv"]
     }
     fn from_extension(ext:&str)->Option<Self>{
         match ext{
             "txt" => Some(Self::Text),
             "json" => Some(Self::Json),
             "serde.json" => Some(Self::SerdeJson),
+            "b" | "bytes" | "raw" => Some(Self::Binary),
+            "csv" => Some(Self::Csv),
+            "tsv" => Some(Self::Tsv),
             _ => None
         }
     }
 }
 
+/// How to serialize real numbers which can't be represented in JSON (NaN, infinity).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NonFiniteRealPolicy{
+    /// Fail with SerializationError
+    #[default]
+    Error,
+    /// Serialize as null (lossy, the value can't be deserialized back)
+    Null,
+}
+
+/// Serialization options.
+/// Each format uses only the options relevant to it; the others are ignored.
+#[derive(Debug, Clone)]
+pub struct SerializeOptions{
+    pub format:String,
+    /// JSON: handling of NaN and infinite real numbers
+    pub non_finite_reals:NonFiniteRealPolicy,
+    /// JSON: indented, multi-line output
+    pub pretty:bool,
+    /// JSON: write real numbers with an integral value as integers (e.g. `4` instead of `4.0`);
+    /// off by default to keep the distinction between integers and reals visible in the output
+    pub integral_reals_as_int:bool,
+    /// CSV/TSV: cell delimiter; if not specified, ',' is used for CSV and tab for TSV
+    pub delimiter:Option<char>,
+}
+
+impl SerializeOptions{
+    /// Default options for the format.
+    pub fn new(format:&str)->Self{
+        SerializeOptions{
+            format:format.to_owned(),
+            non_finite_reals:NonFiniteRealPolicy::default(),
+            pretty:false,
+            integral_reals_as_int:false,
+            delimiter:None,
+        }
+    }
+}
+
 pub trait ValueSerializer where Self:Sized{
     type Formats:SerializationFormats;
     fn type_identifier(&self)->String;
     fn default_extension(&self)->String;
     fn default_media_type(&self)->String;
     fn as_bytes(&self, format:&str)->Result<Vec<u8>, Error>;
+    /// Serialize with options; by default only the format is taken into account.
+    fn as_bytes_with(&self, options:&SerializeOptions)->Result<Vec<u8>, Error>{
+        self.as_bytes(&options.format)
+    }
+    /// Length of the serialized value in bytes, i.e. `as_bytes(format)?.len()`.
+    /// The default implementation serializes the value; implementations may compute it without allocating the bytes.
+    fn serialized_len(&self, format:&str)->Result<usize, Error>{
+        Ok(self.as_bytes(format)?.len())
+    }
+    /// Formats (as extensions) supported by as_bytes and from_bytes.
+    fn supported_formats(&self)->Vec<String>{
+        Self::Formats::iter().map(|x| x.default_extension().to_owned()).collect()
+    }
     fn from_bytes(b: &[u8], format:&str)->Result<Self, Error>;
+    /// Deserialize data from an untrusted source.
+    /// Input longer than max_len bytes is rejected, as well as JSON nested deeper than max_depth,
+    /// before anything is deserialized.
+    fn from_bytes_limited(b: &[u8], format:&str, max_len:usize, max_depth:usize)->Result<Self, Error>{
+        if b.len() > max_len{
+            return Err(Error::LimitExceeded{
+                message:format!("Input too long ({} bytes); maximal length is {}", b.len(), max_len),
+                position:Position::unknown()
+            });
+        }
+        if format == "json" || format == "serde.json"{
+            if let Some(offset) = json_depth_exceeded(b, max_depth){
+                return Err(Error::LimitExceeded{
+                    message:format!("JSON nested too deep; maximal depth is {}", max_depth),
+                    position:Position{offset, ..Position::unknown()}
+                });
+            }
+        }
+        Self::from_bytes(b, format)
+    }
+}
+
+/// Offset of the first JSON array or object nested deeper than max_depth (if any).
+/// Brackets inside strings are ignored.
+fn json_depth_exceeded(b: &[u8], max_depth:usize)->Option<usize>{
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in b.iter().enumerate(){
+        if in_string{
+            match c{
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c{
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > max_depth{
+                    return Some(i);
+                }
+            },
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Information about an evaluation result (see `Environment::eval_with_metadata`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Metadata{
+    pub type_identifier:String,
+    /// Output format (extension)
+    pub format:String,
+    pub media_type:String,
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn csv_tsv_extensions(){
+        assert_eq!(ValueSerializationFormats::from_extension("csv"), Some(ValueSerializationFormats::Csv));
+        assert_eq!(ValueSerializationFormats::from_extension("tsv"), Some(ValueSerializationFormats::Tsv));
+        assert_eq!(ValueSerializationFormats::from_filename("save-data.tsv"), Some(ValueSerializationFormats::Tsv));
+        assert_eq!(ValueSerializationFormats::Csv.default_extension(), "csv");
+        assert_eq!(ValueSerializationFormats::Tsv.media_type(), "text/tab-separated-values");
+        assert_eq!(ValueSerializationFormats::from_filename("data.serde.json"), Some(ValueSerializationFormats::SerdeJson));
+        assert_eq!(ValueSerializationFormats::from_filename("abcsv"), None);
+        assert_eq!(ValueSerializationFormats::from_filename("data.xtsv"), None);
+        assert_eq!(ValueSerializationFormats::extension_from_filename("data-tsv"), None);
+    }
+
+    #[test]
+    fn json_depth(){
+        assert_eq!(json_depth_exceeded(br#"{"a":[1,[2]]}"#, 3), None);
+        assert_eq!(json_depth_exceeded(br#"{"a":[1,[2]]}"#, 2), Some(8));
+        assert_eq!(json_depth_exceeded(br#"["[[[\"[["]"#, 1), None);
+    }
 }
@@ -6,6 +6,7 @@ extern crate serde;
 extern crate serde_json;
 extern crate serde_yaml;
 extern crate percent_encoding;
+extern crate sha2;
 extern crate strum;
 #[macro_use]
 extern crate strum_macros;
@@ -1,8 +1,38 @@
 use crate::error::Error;
+use crate::formats::{media_type_from_extension, Metadata, SerializationFormats, ValueSerializer};
 use std::fmt::Display;
+use std::str::FromStr;
 use std::result::Result;
+use std::sync::Arc;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+
+/// Characters escaped when a query is transported in URL (see `Query::encode_url`).
+pub const URL_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'+')
+    .add(b'/')
+    .add(b':')
+    .add(b'<')
+    .add(b'=')
+    .add(b'>')
+    .add(b'?')
+    .add(b'@')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Position {
     pub offset: usize,
     pub line: u32,
@@ -34,6 +64,11 @@ impl Display for Position {
 pub enum ActionParameter {
     String(String, Position),
     Link(String, Position),
+    /// Reference to a named result (see the `-as-<name>` segment header), e.g. `~V~x~E`.
+    Variable(String, Position),
+    /// Named parameter `name=value` (e.g. `width=100`), written unquoted in the query;
+    /// the text contains both the name and the value. A quoted `'width=100'` is a plain string.
+    Named(String, Position),
 }
 
 impl ActionParameter {
@@ -43,41 +78,261 @@ impl ActionParameter {
     pub fn new_parsed(parameter: String, position: Position) -> ActionParameter {
         ActionParameter::String(parameter, position)
     }
+    /// Named parameter `name=value`; the name should be an identifier.
+    pub fn new_named(name: &str, value: &str) -> ActionParameter {
+        ActionParameter::Named(format!("{}={}", name, value), Position::unknown())
+    }
     pub fn to_string(&self) -> String {
         match self {
             ActionParameter::String(s, _) => s.to_string(),
             ActionParameter::Link(s, _) => s.to_string(),
+            ActionParameter::Variable(s, _) => s.to_string(),
+            ActionParameter::Named(s, _) => s.to_string(),
+        }
+    }
+    /// Kind of the parameter.
+    pub fn kind(&self) -> ParameterKind {
+        match self {
+            ActionParameter::String(_, _) => ParameterKind::String,
+            ActionParameter::Link(_, _) => ParameterKind::Link,
+            ActionParameter::Variable(_, _) => ParameterKind::Variable,
+            ActionParameter::Named(_, _) => ParameterKind::Named,
+        }
+    }
+    /// Position of the parameter in the parsed query.
+    pub fn position(&self) -> &Position {
+        match self {
+            ActionParameter::String(_, position) => position,
+            ActionParameter::Link(_, position) => position,
+            ActionParameter::Variable(_, position) => position,
+            ActionParameter::Named(_, position) => position,
         }
     }
+    /// Name and value of a named parameter (e.g. `width=100`); None for the other kinds of parameters,
+    /// including string parameters containing '='.
+    pub fn named(&self) -> Option<(&str, &str)> {
+        match self {
+            ActionParameter::Named(text, _) => text.split_once('='),
+            _ => None,
+        }
+    }
+    /// Link parameter referring to a query.
+    pub fn link_from_query(query: &Query, position: Position) -> ActionParameter {
+        ActionParameter::Link(query.encode(), position)
+    }
+    /// Encode parameter; string parameters are escaped with `escape_parameter`.
     pub fn encode(&self) -> String {
         match self {
-            ActionParameter::String(s, _) => s.to_string(),
-            ActionParameter::Link(s, _) => panic!("Link not supported yet"),
+            ActionParameter::String(s, _) => escape_parameter(s),
+            ActionParameter::Link(s, _) => format!("~X~{}~E", s),
+            ActionParameter::Variable(s, _) => format!("~V~{}~E", s),
+            ActionParameter::Named(s, _) => match s.split_once('=') {
+                Some((name, value)) => format!(
+                    "{}={}",
+                    name,
+                    utf8_percent_encode(value, NAMED_VALUE_ENCODE_SET)
+                ),
+                None => escape_parameter(s),
+            },
         }
     }
 }
 
+/// Characters percent-encoded in the value of a named parameter, which can't be quoted.
+const NAMED_VALUE_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'_').remove(b'=');
+
+/// Escape a string parameter as it appears in the encoded query (inverse of `crate::parse::unescape_parameter`).
+/// Parameters consisting only of alphanumeric characters and '_' are kept as they are,
+/// anything else is quoted (e.g. `'a-b'`), with the quote written as `''`.
+/// Thus a string containing '=' is quoted and does not turn into a named parameter.
+pub fn escape_parameter(raw: &str) -> String {
+    if raw.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        raw.to_owned()
+    } else {
+        format!("'{}'", raw.replace('\'', "''"))
+    }
+}
+
+/// Encode parameters separated by '-'.
+/// An empty string parameter is written as is (e.g. `add--5`), except the last one, which is quoted (`range-''`),
+/// since a query can't end with a dangling '-'.
+fn encode_parameters(parameters: &[ActionParameter]) -> String {
+    parameters
+        .iter()
+        .enumerate()
+        .map(|(i, parameter)| match parameter {
+            ActionParameter::String(s, _) if s.is_empty() && i + 1 == parameters.len() => "''".to_owned(),
+            _ => parameter.encode(),
+        })
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// Kind of an action parameter.
+/// More kinds may be added in the future, hence the enum is non-exhaustive.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ParameterKind {
+    String,
+    Link,
+    Variable,
+    Named,
+}
+
+/// Detailed information about a single action parameter.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParamDetail {
+    pub index: usize,
+    /// Parameter as it appears in the encoded query
+    pub raw: String,
+    /// Parameter value (unescaped)
+    pub decoded: String,
+    pub position: Position,
+    pub kind: ParameterKind,
+}
+
+/// Name of an action in the query AST.
+/// The name is reference counted, so that names parsed with an interner (see `crate::parse::parse_interned`)
+/// share a single allocation. It dereferences to `str` and is serialized as a plain string.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[serde(from = "String", into = "String")]
+pub struct ActionName(Arc<str>);
+
+impl ActionName {
+    pub fn new(name: &str) -> Self {
+        ActionName(Arc::from(name))
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    /// True if both names share the same allocation.
+    pub fn ptr_eq(&self, other: &ActionName) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl std::ops::Deref for ActionName {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::borrow::Borrow<str> for ActionName {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for ActionName {
+    fn from(name: &str) -> Self {
+        ActionName::new(name)
+    }
+}
+
+impl From<String> for ActionName {
+    fn from(name: String) -> Self {
+        ActionName(Arc::from(name))
+    }
+}
+
+impl From<ActionName> for String {
+    fn from(name: ActionName) -> Self {
+        name.0.to_string()
+    }
+}
+
+impl PartialEq<str> for ActionName {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for ActionName {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for ActionName {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl Display for ActionName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::fmt::Debug for ActionName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ActionRequest {
-    pub name: String,
+    pub name: ActionName,
     pub position: Position,
+    /// Position right after the end of the action (i.e. exclusive); unknown if missing in the serialized form
+    #[serde(default)]
+    pub end_position: Position,
     pub parameters: Vec<ActionParameter>,
 }
 
 impl ActionRequest {
+    /// Action request without parameters and with unknown position;
+    /// parameters can be added by chaining, e.g. `ActionRequest::new("add").param("1").link("x/y")`.
+    pub fn new(name: &str) -> ActionRequest {
+        ActionRequest {
+            name: name.into(),
+            position: Position::unknown(),
+            end_position: Position::unknown(),
+            parameters: vec![],
+        }
+    }
+    /// Append a string parameter.
+    pub fn param(mut self, parameter: &str) -> Self {
+        self.parameters.push(ActionParameter::new(parameter));
+        self
+    }
+    /// Append a named parameter `name=value`.
+    pub fn named(mut self, name: &str, value: &str) -> Self {
+        self.parameters
+            .push(ActionParameter::new_named(name, value));
+        self
+    }
+    /// Append a link parameter referring to the (encoded) query.
+    pub fn link(mut self, query: &str) -> Self {
+        self.parameters
+            .push(ActionParameter::Link(query.to_owned(), Position::unknown()));
+        self
+    }
+    /// Set the position of the action.
+    pub fn at(mut self, position: Position) -> Self {
+        self.position = position;
+        self
+    }
+    pub fn parameter_details(&self) -> Vec<ParamDetail> {
+        self.parameters
+            .iter()
+            .enumerate()
+            .map(|(index, parameter)| ParamDetail {
+                index,
+                raw: parameter.encode(),
+                decoded: parameter.to_string(),
+                position: parameter.position().clone(),
+                kind: parameter.kind(),
+            })
+            .collect()
+    }
     pub fn encode(&self) -> String {
         if self.parameters.is_empty() {
-            self.name.to_owned()
+            self.name.to_string()
         } else {
-            format!(
-                "{}-{}",
-                self.name,
-                self.parameters
-                    .iter()
-                    .map(|x| x.encode())
-                    .collect::<Vec<String>>()
-                    .join("-")
-            )
+            format!("{}-{}", self.name, encode_parameters(&self.parameters))
         }
     }
 }
@@ -86,16 +341,21 @@ impl ActionRequest {
 pub struct SegmentHeader {
     pub name: String,
     pub level: usize,
+    /// Position of the header start (the first '-')
     pub position: Position,
+    /// Position of the name (right after the '-' level indicator); unknown if missing in the serialized form
+    #[serde(default)]
+    pub name_position: Position,
     pub parameters: Vec<ActionParameter>,
 }
 
 impl SegmentHeader {
-    pub fn new_parsed_minimal(level: usize, position: Position) -> Self {
+    pub fn new_parsed_minimal(level: usize, position: Position, name_position: Position) -> Self {
         SegmentHeader {
             name: String::new(),
             level: level,
             position: position,
+            name_position,
             parameters: vec![],
         }
     }
@@ -105,9 +365,10 @@ impl SegmentHeader {
         action_request: &ActionRequest,
     ) -> Self {
         SegmentHeader {
-            name: action_request.name.to_owned(),
+            name: action_request.name.to_string(),
             level: level,
             position: position,
+            name_position: action_request.position.clone(),
             parameters: action_request.parameters.clone(),
         }
     }
@@ -120,10 +381,8 @@ impl SegmentHeader {
         encoded.push_str(&self.name);
         if !self.parameters.is_empty() {
             assert!(self.name.len()>0);
-            for parameter in self.parameters.iter() {
-                encoded.push_str("-");
-                encoded.push_str(&parameter.encode())
-            }
+            encoded.push_str("-");
+            encoded.push_str(&encode_parameters(&self.parameters));
         }
         encoded
     }
@@ -148,6 +407,23 @@ impl QuerySegment {
             query: query,
         }
     }
+    /// Segment with the header replaced (actions are kept).
+    pub fn with_header(self, header: SegmentHeader) -> QuerySegment {
+        QuerySegment {
+            header: Some(header),
+            query: self.query,
+        }
+    }
+    /// Segment without the header (actions are kept).
+    pub fn without_header(self) -> QuerySegment {
+        QuerySegment {
+            header: None,
+            query: self.query,
+        }
+    }
+    /// Encode segment as header and actions separated by '/'.
+    /// A minimal header (level 1, no name) is encoded as a lone `-`, e.g. `-/b`;
+    /// the parser reads a lone `-` element always as such a header, so the encoding round-trips.
     pub fn encode(&self) -> String {
         let query = self
             .query
@@ -171,11 +447,18 @@ impl QuerySegment {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Query {
     pub segments: Vec<QuerySegment>,
+    /// Optional filename at the end of the query (e.g. `data.csv` in `abc/data.csv`);
+    /// it is not evaluated, its extension is a hint of the output format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
 }
 
 impl Query {
     pub fn new() -> Query {
-        Query { segments: vec![] }
+        Query {
+            segments: vec![],
+            filename: None,
+        }
     }
 
     pub fn add_segment(&mut self, name: &str) -> &mut QuerySegment {
@@ -184,6 +467,7 @@ impl Query {
                 name: name.to_owned(),
                 level: 1,
                 position: Position::unknown(),
+                name_position: Position::unknown(),
                 parameters: vec![],
             }),
             vec![],
@@ -191,19 +475,457 @@ impl Query {
         self.segments.push(qs);
         self.segments.last_mut().unwrap()
     }
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+    /// Segment at the index (if present).
+    pub fn segment(&self, i: usize) -> Option<&QuerySegment> {
+        self.segments.get(i)
+    }
+    /// Mutable segment at the index (if present).
+    pub fn segment_mut(&mut self, i: usize) -> Option<&mut QuerySegment> {
+        self.segments.get_mut(i)
+    }
+    /// Append action to the last segment; if there is no segment, a segment without header is created.
+    pub fn append_action(&mut self, name: &str, params: &[&str]) -> &mut ActionRequest {
+        if self.segments.is_empty() {
+            self.segments.push(QuerySegment::new());
+        }
+        let segment = self.segments.last_mut().unwrap();
+        segment.query.push(
+            params
+                .iter()
+                .fold(ActionRequest::new(name), |request, x| request.param(x)),
+        );
+        segment.query.last_mut().unwrap()
+    }
+    /// Insert segment at the front of the query, e.g. to run a preprocessing segment first.
+    /// Only the first segment may be without header, so the former first segment gets
+    /// a minimal header (`-`) if it had none; this keeps the encoded query parseable with the same structure.
+    /// An empty segment without header has no effect and is not inserted.
+    pub fn prepend_segment(&mut self, segment: QuerySegment) {
+        if segment.header.is_none() && segment.query.is_empty() {
+            return;
+        }
+        if let Some(first) = self.segments.first_mut() {
+            if first.header.is_none() {
+                first.header = Some(SegmentHeader::new_parsed_minimal(
+                    1,
+                    Position::unknown(),
+                    Position::unknown(),
+                ));
+            }
+        }
+        self.segments.insert(0, segment);
+    }
+    /// Remove trailing empty segments (segments without header and actions).
+    pub fn trim(&mut self) {
+        while let Some(segment) = self.segments.last() {
+            if segment.header.is_none() && segment.query.is_empty() {
+                self.segments.pop();
+            } else {
+                break;
+            }
+        }
+    }
+    /// Apply a function to the values of all the string parameters (including the parameters of segment headers).
+    /// Links are left unchanged.
+    pub fn map_parameters(&mut self, mut f: impl FnMut(&str) -> String) {
+        for segment in self.segments.iter_mut() {
+            let header_parameters = segment
+                .header
+                .iter_mut()
+                .flat_map(|header| header.parameters.iter_mut());
+            let action_parameters = segment
+                .query
+                .iter_mut()
+                .flat_map(|action| action.parameters.iter_mut());
+            for parameter in header_parameters.chain(action_parameters) {
+                match parameter {
+                    ActionParameter::String(value, _) => *value = f(value),
+                    ActionParameter::Named(text, _) => {
+                        if let Some((name, value)) = text.split_once('=') {
+                            *text = format!("{}={}", name, f(value));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    /// Rename all the actions (and segment headers) named `from` to `to`, including those inside links.
+    /// Returns the number of renamed occurrences.
+    pub fn rename_action(&mut self, from: &str, to: &str) -> usize {
+        let mut count = 0;
+        for segment in self.segments.iter_mut() {
+            if let Some(header) = segment.header.as_mut() {
+                if header.name == from {
+                    header.name = to.to_owned();
+                    count += 1;
+                }
+            }
+            for action in segment.query.iter_mut() {
+                if action.name == from {
+                    action.name = to.into();
+                    count += 1;
+                }
+            }
+            let header_parameters = segment
+                .header
+                .iter_mut()
+                .flat_map(|header| header.parameters.iter_mut());
+            let action_parameters = segment
+                .query
+                .iter_mut()
+                .flat_map(|action| action.parameters.iter_mut());
+            for parameter in header_parameters.chain(action_parameters) {
+                if let ActionParameter::Link(link, _) = parameter {
+                    if let Ok(mut query) = crate::parse::parse(link) {
+                        let renamed = query.rename_action(from, to);
+                        if renamed > 0 {
+                            *link = query.encode();
+                            count += renamed;
+                        }
+                    }
+                }
+            }
+        }
+        count
+    }
+    /// SHA-256 hash of the encoded query, suitable as a fixed-size cache key.
+    /// Positions are not part of the encoding, hence they don't influence the fingerprint.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let mut fingerprint = [0u8; 32];
+        fingerprint.copy_from_slice(&Sha256::digest(self.encode().as_bytes()));
+        fingerprint
+    }
+    /// Encode query as a single URL-safe string (including the '/' separators).
+    /// The result can be parsed back by `parse_url_query`.
+    pub fn encode_url(&self) -> String {
+        utf8_percent_encode(&self.encode(), URL_ENCODE_SET).to_string()
+    }
+    /// Encode query as a string.
+    ///
+    /// Round-trip invariant: for any string accepted by `crate::parse::parse`, the encoded parsed query
+    /// parses to a query with the same structure (headers, actions, parameters and filename; positions aside),
+    /// and encoding that query again gives the same string.
+    /// The encoding is canonical, so it may differ from the original string in escaping,
+    /// e.g. `a-b%20c` is encoded as `a-'b c'`.
     pub fn encode(&self) -> String {
-        self.segments
+        let segments = self
+            .segments
             .iter()
             .map(|x| x.encode())
             .collect::<Vec<_>>()
-            .join("/")
+            .join("/");
+        match &self.filename {
+            Some(filename) if segments.is_empty() => filename.to_owned(),
+            Some(filename) => format!("{}/{}", segments, filename),
+            None => segments,
+        }
+    }
+    /// Encode query omitting the leading minimal header, e.g. `abc/-x/y` instead of `-/abc/-x/y`.
+    ///
+    /// The marker is omitted only if the first segment has a minimal header (`-`: level 1, no name, no parameters)
+    /// and at least one action; otherwise the result is the same as `encode`.
+    /// The result is meant to be read back with `crate::parse::parse_relative`, which restores the minimal header.
+    /// Reading it with `parse` yields a first segment without header, which is equivalent for evaluation.
+    pub fn encode_relative(&self) -> String {
+        match self.segments.first() {
+            Some(QuerySegment {
+                header: Some(header),
+                query,
+            }) if header.level == 1
+                && header.name.is_empty()
+                && header.parameters.is_empty()
+                && !query.is_empty() =>
+            {
+                let encoded = self.encode();
+                encoded[2..].to_owned()
+            }
+            _ => self.encode(),
+        }
+    }
+    /// Query with a filename selecting the output format, e.g. `abc/data.csv` for `abc` and `csv`.
+    /// An existing filename keeps its name and gets the new extension (`abc/out.json` becomes `abc/out.csv`),
+    /// so that there is always only one format hint.
+    pub fn with_format(&self, extension: &str) -> Query {
+        let name = self
+            .filename
+            .as_ref()
+            .map(|filename| filename.split('.').next().unwrap_or_default())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("data");
+        Query {
+            segments: self.segments.clone(),
+            filename: Some(format!("{}.{}", name, extension)),
+        }
+    }
+    /// Extension of the filename (if present), i.e. everything after the first '.',
+    /// e.g. `csv` for `abc/data.csv` or `serde.json` for `abc/data.serde.json`.
+    /// None if there is no filename or the filename has no '.'.
+    pub fn extension(&self) -> Option<&str> {
+        self.filename
+            .as_ref()
+            .and_then(|filename| filename.split_once('.'))
+            .map(|(_, extension)| extension)
+    }
+}
+impl Display for ActionRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.encode())
+    }
+}
+
+impl Display for SegmentHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.encode())
+    }
+}
+
+impl Display for QuerySegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.encode())
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.encode())
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::parse::parse(s)
     }
 }
+
+impl FromStr for ActionRequest {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::parse::parse_action_request(s)
+    }
+}
+
+/// Action parameters, consumed one by one when converted to the action arguments.
 #[derive(Debug)]
-pub struct ActionParametersSlice<'a>(pub &'a [ActionParameter]);
+pub struct ActionParametersSlice<'a> {
+    parameters: &'a [ActionParameter],
+    index: usize,
+}
+
+impl<'a> ActionParametersSlice<'a> {
+    pub fn new(parameters: &'a [ActionParameter]) -> Self {
+        ActionParametersSlice {
+            parameters,
+            index: 0,
+        }
+    }
+    /// Index of the next parameter to be consumed.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    /// Parameters which were not consumed yet.
+    pub fn remaining(&self) -> &'a [ActionParameter] {
+        &self.parameters[self.index.min(self.parameters.len())..]
+    }
+    /// Current position, which can be restored later to undo the consumption of parameters.
+    pub fn checkpoint(&self) -> usize {
+        self.index
+    }
+    /// Return to a checkpoint; parameters consumed after the checkpoint will be consumed again.
+    pub fn restore(&mut self, checkpoint: usize) {
+        self.index = checkpoint.min(self.parameters.len());
+    }
+    /// Value of a named parameter `name=value` (among all the parameters, see `ActionParameter::named`),
+    /// e.g. `1` for `width` in `resize-width=1`.
+    /// Parsing rejects duplicate names; a name given more than once in parameters constructed otherwise
+    /// is ambiguous as well and results in a ParameterError pointing at the second occurrence.
+    pub fn get_named(&self, name: &str) -> Result<Option<String>, Error> {
+        let mut found: Option<String> = None;
+        for parameter in self.parameters.iter() {
+            if let Some((key, value)) = parameter.named() {
+                if key == name {
+                    if found.is_some() {
+                        return Err(Error::ParameterError {
+                            message: format!("duplicate parameter '{}'", name),
+                            position: parameter.position().clone(),
+                        });
+                    }
+                    found = Some(value.to_owned());
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// JSON Schema (draft-07) of the serialized `Query` (as produced by serde_json),
+/// allowing other languages to validate the exchanged query AST.
+pub fn query_json_schema() -> serde_json::Value {
+    let position = serde_json::json!({"$ref": "#/definitions/Position"});
+    let parameters = serde_json::json!({
+        "type": "array",
+        "items": {"$ref": "#/definitions/ActionParameter"}
+    });
+    let tagged_parameter = |tag: &str| {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                tag: {
+                    "type": "array",
+                    "items": [{"type": "string"}, position],
+                    "minItems": 2,
+                    "maxItems": 2
+                }
+            },
+            "required": [tag],
+            "additionalProperties": false
+        })
+    };
+    serde_json::json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Query",
+        "type": "object",
+        "properties": {
+            "segments": {
+                "type": "array",
+                "items": {"$ref": "#/definitions/QuerySegment"}
+            },
+            "filename": {"type": "string"}
+        },
+        "required": ["segments"],
+        "definitions": {
+            "Position": {
+                "type": "object",
+                "properties": {
+                    "offset": {"type": "integer", "minimum": 0},
+                    "line": {"type": "integer", "minimum": 0},
+                    "column": {"type": "integer", "minimum": 0}
+                },
+                "required": ["offset", "line", "column"]
+            },
+            "ActionParameter": {
+                "oneOf": [
+                    tagged_parameter("String"),
+                    tagged_parameter("Link"),
+                    tagged_parameter("Variable"),
+                    tagged_parameter("Named")
+                ]
+            },
+            "ActionRequest": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "position": position,
+                    "end_position": position,
+                    "parameters": parameters
+                },
+                "required": ["name", "position", "parameters"]
+            },
+            "SegmentHeader": {
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "level": {"type": "integer", "minimum": 1},
+                    "position": position,
+                    "name_position": position,
+                    "parameters": parameters
+                },
+                "required": ["name", "level", "position", "parameters"]
+            },
+            "QuerySegment": {
+                "type": "object",
+                "properties": {
+                    "header": {
+                        "oneOf": [{"type": "null"}, {"$ref": "#/definitions/SegmentHeader"}]
+                    },
+                    "query": {
+                        "type": "array",
+                        "items": {"$ref": "#/definitions/ActionRequest"}
+                    }
+                },
+                "required": ["header", "query"]
+            }
+        }
+    })
+}
 
 pub trait Environment<T> {
     fn eval(&mut self, input: T, query: &str) -> Result<T, Error>;
+
+    /// Evaluate query and describe the result.
+    /// If the query ends with a filename (e.g. `square/out.csv`, see `Query::extension`),
+    /// it is not evaluated, but its extension determines the output format;
+    /// otherwise the default format of the resulting value is used.
+    /// An extension not supported by the value type is an error.
+    fn eval_with_metadata(&mut self, input: T, query: &str) -> Result<(T, Metadata), Error>
+    where
+        T: ValueSerializer,
+    {
+        self.eval_with_metadata_hint(input, query, None)
+    }
+
+    /// Evaluate query with a metadata hint describing the input (e.g. the media type of bytes),
+    /// which the actions can consult. By default the hint is ignored.
+    fn eval_with_hint(&mut self, input: T, query: &str, _hint: Option<Metadata>) -> Result<T, Error> {
+        self.eval(input, query)
+    }
+
+    /// Same as `eval_with_metadata`, passing the input metadata hint to the evaluation (see `eval_with_hint`).
+    fn eval_with_metadata_hint(
+        &mut self,
+        input: T,
+        query: &str,
+        hint: Option<Metadata>,
+    ) -> Result<(T, Metadata), Error>
+    where
+        T: ValueSerializer,
+    {
+        let mut parsed = crate::parse::parse(query)?;
+        let extension = parsed.extension().map(|x| x.to_owned());
+        if let Some(extension) = &extension {
+            if T::Formats::from_extension(extension).is_none() {
+                return Err(Error::SerializationError {
+                    message: format!("Unsupported format {}", extension),
+                    format: extension.to_owned(),
+                });
+            }
+        }
+        let value = match &extension {
+            Some(_) => {
+                parsed.filename = None;
+                self.eval_with_hint(input, &parsed.encode(), hint)?
+            }
+            None => self.eval_with_hint(input, query, hint)?,
+        };
+        let format = extension.unwrap_or_else(|| value.default_extension());
+        let metadata = Metadata {
+            type_identifier: value.type_identifier(),
+            // Compound extensions (e.g. serde.json) are identified by the last part
+            media_type: media_type_from_extension(format.rsplit('.').next().unwrap_or_default())
+                .to_owned(),
+            format,
+        };
+        Ok((value, metadata))
+    }
+
+    /// Evaluate query and serialize the result in the format given by the extension (see `Query::with_format`).
+    fn eval_to_bytes(
+        &mut self,
+        input: T,
+        query: &str,
+        extension: &str,
+    ) -> Result<(Vec<u8>, Metadata), Error>
+    where
+        T: ValueSerializer,
+    {
+        let query = crate::parse::parse(query)?.with_format(extension).encode();
+        let (value, metadata) = self.eval_with_metadata(input, &query)?;
+        Ok((value.as_bytes(&metadata.format)?, metadata))
+    }
 }
 
 pub trait TryActionParametersInto<T, E> {
@@ -224,28 +946,80 @@ impl TryParameterFrom for i32 {
     }
 }
 
+impl TryParameterFrom for f64 {
+    fn try_parameter_from(text: &str) -> Result<Self, String> {
+        text.parse()
+            .map_err(|_| format!("Can't parse '{}' as real number", text))
+    }
+}
+
+impl TryParameterFrom for bool {
+    fn try_parameter_from(text: &str) -> Result<Self, String> {
+        match &text.to_lowercase()[..] {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("Can't parse '{}' as bool", text)),
+        }
+    }
+}
+
 impl TryParameterFrom for String {
     fn try_parameter_from(text: &str) -> Result<Self, String> {
         Ok(text.to_owned())
     }
 }
 
+/// Implement the parameter and value conversions for a newtype wrapping a primitive type,
+/// e.g. `struct Celsius(f64); impl_parameter_newtype!(Celsius, f64);`.
+/// Generates `TryParameterFrom`, `InputType`, `From<newtype> for Value` and `TryFrom<Value>` for the newtype,
+/// all delegating to the inner type, so that the newtype can be used as a parameter, an input and an output of actions.
+#[macro_export]
+macro_rules! impl_parameter_newtype {
+    ($name:ident, $inner:ty) => {
+        impl $crate::query::TryParameterFrom for $name {
+            fn try_parameter_from(text: &str) -> ::std::result::Result<Self, String> {
+                <$inner as $crate::query::TryParameterFrom>::try_parameter_from(text).map($name)
+            }
+        }
+        impl $crate::action_registry::InputType for $name {
+            fn input_type_identifier() -> &'static str {
+                <$inner as $crate::action_registry::InputType>::input_type_identifier()
+            }
+        }
+        impl ::std::convert::From<$name> for $crate::value::Value {
+            fn from(value: $name) -> Self {
+                $crate::value::Value::from(value.0)
+            }
+        }
+        impl ::std::convert::TryFrom<$crate::value::Value> for $name {
+            type Error = $crate::error::Error;
+            fn try_from(value: $crate::value::Value) -> ::std::result::Result<Self, Self::Error> {
+                <$inner as ::std::convert::TryFrom<$crate::value::Value>>::try_from(value)
+                    .map($name)
+            }
+        }
+    };
+}
+
 impl<'a, T, E> TryActionParametersInto<T, E> for ActionParametersSlice<'a>
 where
     T: TryParameterFrom,
 {
     fn try_parameters_into(&mut self, env: &mut E) -> Result<T, Error> {
-        if self.0.is_empty() {
-            Err(Error::ArgumentNotSpecified)
+        if self.index >= self.parameters.len() {
+            Err(Error::ArgumentNotSpecified {
+                index: self.index,
+                action: None,
+            })
         } else {
-            match &self.0[0] {
-                ActionParameter::String(x, position) => {
+            match &self.parameters[self.index] {
+                ActionParameter::String(x, position) | ActionParameter::Named(x, position) => {
                     let v: T =
                         T::try_parameter_from(&x).map_err(|message| Error::ParameterError {
                             message,
                             position: position.clone(),
                         })?;
-                    self.0 = &self.0[1..];
+                    self.index += 1;
                     Ok(v)
                 }
                 _ => Err(Error::General {
@@ -263,7 +1037,7 @@ mod tests {
     #[test]
     fn parameters_into_i32() -> Result<(), Box<dyn std::error::Error>> {
         let v = [ActionParameter::new("123"), ActionParameter::new("234")];
-        let mut par = ActionParametersSlice(&v[..]);
+        let mut par = ActionParametersSlice::new(&v[..]);
         let x: i32 = par.try_parameters_into(&mut ())?;
         assert_eq!(x, 123);
         let x: i32 = par.try_parameters_into(&mut ())?;
@@ -271,9 +1045,27 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn parameters_checkpoint_restore() -> Result<(), Box<dyn std::error::Error>> {
+        let v = [ActionParameter::new("abc"), ActionParameter::new("234")];
+        let mut par = ActionParametersSlice::new(&v[..]);
+        let checkpoint = par.checkpoint();
+        let x: Result<i32, Error> = par.try_parameters_into(&mut ());
+        assert!(x.is_err());
+        let x: String = par.try_parameters_into(&mut ())?;
+        assert_eq!(x, "abc");
+        assert_eq!(par.remaining().len(), 1);
+        par.restore(checkpoint);
+        assert_eq!(par.index(), 0);
+        let x: String = par.try_parameters_into(&mut ())?;
+        assert_eq!(x, "abc");
+        let x: i32 = par.try_parameters_into(&mut ())?;
+        assert_eq!(x, 234);
+        Ok(())
+    }
+    #[test]
     fn parameters_into_str() -> Result<(), Box<dyn std::error::Error>> {
         let v = [ActionParameter::new("123"), ActionParameter::new("234")];
-        let mut par = ActionParametersSlice(&v[..]);
+        let mut par = ActionParametersSlice::new(&v[..]);
         let x: String = par.try_parameters_into(&mut ())?;
         assert_eq!(x, "123");
         let x: i32 = par.try_parameters_into(&mut ())?;
@@ -293,4 +1085,345 @@ mod tests {
         assert_eq!(query.encode(), "-test");
         Ok(())
     }
+    #[test]
+    fn map_parameters_redact() -> Result<(), Box<dyn std::error::Error>> {
+        let mut query = crate::parse::parse("abc-def-1/-seg-x/xyz-2/q")?;
+        query.map_parameters(|_| "***".to_owned());
+        assert_eq!(query.encode(), "abc-'***'-'***'/-seg-'***'/xyz-'***'/q");
+        Ok(())
+    }
+    #[test]
+    fn with_format() -> Result<(), Box<dyn std::error::Error>> {
+        let query = crate::parse::parse("abc/def")?;
+        assert_eq!(query.with_format("csv").encode(), "abc/def/data.csv");
+        let query = crate::parse::parse("abc/out.json")?;
+        assert_eq!(query.with_format("csv").encode(), "abc/out.csv");
+        assert_eq!(
+            query.with_format("csv").with_format("txt").encode(),
+            "abc/out.txt"
+        );
+        assert_eq!(Query::new().with_format("csv").encode(), "data.csv");
+        Ok(())
+    }
+    #[test]
+    fn prepend_segment() -> Result<(), Box<dyn std::error::Error>> {
+        let wrapper = crate::parse::parse("-pre/load-x")?.segments.remove(0);
+        let mut query = crate::parse::parse("abc/def/-x/y")?;
+        query.prepend_segment(wrapper);
+        assert_eq!(query.encode(), "-pre/load-x/-/abc/def/-x/y");
+        assert_eq!(
+            crate::parse::parse(&query.encode())?.encode(),
+            query.encode()
+        );
+
+        let mut query = crate::parse::parse("abc/out.csv")?;
+        query.prepend_segment(QuerySegment::new_from(
+            None,
+            vec![ActionRequest::new("load")],
+        ));
+        assert_eq!(query.encode(), "load/-/abc/out.csv");
+        assert_eq!(crate::parse::parse(&query.encode())?.segments.len(), 2);
+        query.prepend_segment(QuerySegment::new());
+        assert_eq!(query.encode(), "load/-/abc/out.csv");
+        Ok(())
+    }
+    #[test]
+    fn duplicate_named_parameters() -> Result<(), Box<dyn std::error::Error>> {
+        let query = crate::parse::parse("resize-width=1-height=2-'depth=3'-x")?;
+        let parameters = ActionParametersSlice::new(&query.segments[0].query[0].parameters);
+        assert_eq!(parameters.get_named("width")?, Some("1".to_owned()));
+        assert_eq!(parameters.get_named("height")?, Some("2".to_owned()));
+        // Quoted parameter is a plain string
+        assert_eq!(parameters.get_named("depth")?, None);
+        assert_eq!(
+            query.segments[0].query[0].parameters[2].kind(),
+            ParameterKind::String
+        );
+        assert_eq!(query.encode(), "resize-width=1-height=2-'depth=3'-x");
+        assert!(crate::parse::parse("echo-'x=1'-'x=2'").is_ok());
+
+        match crate::parse::parse("resize-width=1-width=2") {
+            Err(Error::ParameterError { message, position }) => {
+                assert_eq!(message, "duplicate parameter 'width'");
+                assert_eq!(position.offset, 15);
+            }
+            x => panic!("ParameterError expected, got {:?}", x),
+        }
+        assert!(crate::parse::parse("resize-width=1-height=1").is_ok());
+
+        let request = ActionRequest::new("resize")
+            .named("width", "1")
+            .named("width", "2");
+        let parameters = ActionParametersSlice::new(&request.parameters);
+        match parameters.get_named("width") {
+            Err(Error::ParameterError { message, .. }) => {
+                assert_eq!(message, "duplicate parameter 'width'");
+            }
+            x => panic!("ParameterError expected, got {:?}", x),
+        }
+        Ok(())
+    }
+    #[test]
+    fn literal_equals_parameter_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let mut query = Query::new();
+        query.append_action("f", &["a=1", "a=2"]);
+        assert_eq!(query.encode(), "f-'a=1'-'a=2'");
+        let parsed = crate::parse::parse(&query.encode())?;
+        assert_eq!(parsed.encode(), query.encode());
+        assert_eq!(parsed.segments[0].query[0].parameters[0].to_string(), "a=1");
+        assert_eq!(parsed.segments[0].query[0].parameters[0].named(), None);
+
+        let request = ActionRequest::new("f").named("a", "x y");
+        assert_eq!(request.encode(), "f-a=x%20y");
+        let parsed = crate::parse::parse(&request.encode())?;
+        assert_eq!(
+            parsed.segments[0].query[0].parameters[0].named(),
+            Some(("a", "x y"))
+        );
+        Ok(())
+    }
+    #[test]
+    fn action_request_builder() {
+        let request = ActionRequest::new("add")
+            .param("1")
+            .param("a b")
+            .link("x/y-2")
+            .at(Position {
+                offset: 4,
+                line: 1,
+                column: 5,
+            });
+        assert_eq!(request.encode(), "add-1-'a b'-~X~x/y-2~E");
+        assert_eq!(request.position.offset, 4);
+        assert_eq!(request.parameters[2].kind(), ParameterKind::Link);
+        assert_eq!(ActionRequest::new("x").encode(), "x");
+    }
+    #[test]
+    fn encode_url_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let query = crate::parse::parse("-abc/x-y-z/-/q-1")?;
+        let url = query.encode_url();
+        assert!(!url.contains('/'));
+        let decoded = crate::parse::parse_url_query(&url)?;
+        assert_eq!(decoded.encode(), query.encode());
+        Ok(())
+    }
+    #[test]
+    fn fingerprint() -> Result<(), Box<dyn std::error::Error>> {
+        let mut query = Query::new();
+        query.add_segment("test");
+        let parsed = crate::parse::parse("-test")?;
+        assert_eq!(query.fingerprint(), parsed.fingerprint());
+        let other = crate::parse::parse("-test/x")?;
+        assert_ne!(query.fingerprint(), other.fingerprint());
+        Ok(())
+    }
+    #[test]
+    fn trim_query() -> Result<(), Box<dyn std::error::Error>> {
+        let mut query = crate::parse::parse("abc/-x/def")?;
+        query.segments.push(QuerySegment::new());
+        query.segments.push(QuerySegment::new());
+        assert_eq!(query.encode(), "abc/-x/def//");
+        query.trim();
+        assert_eq!(query.segments.len(), 2);
+        assert_eq!(query.encode(), "abc/-x/def");
+        query.trim();
+        assert_eq!(query.encode(), "abc/-x/def");
+        Ok(())
+    }
+    #[test]
+    fn append_action() -> Result<(), Box<dyn std::error::Error>> {
+        let mut query = Query::new();
+        query.append_action("abc", &[]);
+        query.append_action("add", &["1", "2"]);
+        assert_eq!(query.encode(), "abc/add-1-2");
+        let mut query = Query::new();
+        query.add_segment("test");
+        query.append_action("x", &["y"]);
+        assert_eq!(query.encode(), "-test/x-y");
+        Ok(())
+    }
+    #[test]
+    fn parameter_details() -> Result<(), Box<dyn std::error::Error>> {
+        let mut query = crate::parse::parse("abc/add-1-x")?;
+        let action = &mut query.segments[0].query[1];
+        action
+            .parameters
+            .push(ActionParameter::Link("abc".to_owned(), Position::unknown()));
+        let details = action.parameter_details();
+        assert_eq!(details.len(), 3);
+        assert_eq!(details[0].index, 0);
+        assert_eq!(details[0].decoded, "1");
+        assert_eq!(details[0].raw, "1");
+        assert_eq!(details[0].position.offset, 8);
+        assert_eq!(details[0].kind, ParameterKind::String);
+        assert_eq!(details[1].decoded, "x");
+        assert_eq!(details[1].position.offset, 10);
+        assert_eq!(details[2].index, 2);
+        assert_eq!(details[2].raw, "~X~abc~E");
+        assert_eq!(details[2].decoded, "abc");
+        assert_eq!(details[2].kind, ParameterKind::Link);
+        Ok(())
+    }
+    #[test]
+    fn link_from_query() -> Result<(), Box<dyn std::error::Error>> {
+        let mut link = Query::new();
+        link.add_segment("data");
+        link.append_action("load", &["x"]);
+        let mut query = Query::new();
+        query
+            .append_action("join", &[])
+            .parameters
+            .push(ActionParameter::link_from_query(&link, Position::unknown()));
+        assert_eq!(query.encode(), "join-~X~-data/load-x~E");
+        let parsed = crate::parse::parse(&query.encode())?;
+        match &parsed.segments[0].query[0].parameters[0] {
+            ActionParameter::Link(s, _) => assert_eq!(s, &link.encode()),
+            _ => panic!("Link expected"),
+        }
+        assert_eq!(parsed.encode(), query.encode());
+        Ok(())
+    }
+    #[test]
+    fn from_str() -> Result<(), Box<dyn std::error::Error>> {
+        let query = "abc-def/x".parse::<Query>()?;
+        assert_eq!(query.segments[0].query.len(), 2);
+        assert_eq!(query.encode(), "abc-def/x");
+        assert!("abc//x".parse::<Query>().is_err());
+        let action = "add-1-2".parse::<ActionRequest>()?;
+        assert_eq!(action.name, "add");
+        assert_eq!(action.parameters.len(), 2);
+        assert!("add-1/x".parse::<ActionRequest>().is_err());
+        assert!("1add".parse::<ActionRequest>().is_err());
+        Ok(())
+    }
+    #[test]
+    fn display() -> Result<(), Box<dyn std::error::Error>> {
+        let query: Query = "abc-def/-seg-1/x-~X~y~E".parse()?;
+        assert_eq!(format!("{}", query), query.encode());
+        assert_eq!(query.to_string(), "abc-def/-seg-1/x-~X~y~E");
+        assert_eq!(format!("{}", query.segments[1]), "-seg-1/x-~X~y~E");
+        assert_eq!(
+            format!("{}", query.segments[1].header.as_ref().unwrap()),
+            "-seg-1"
+        );
+        assert_eq!(format!("{}", query.segments[0].query[0]), "abc-def");
+        Ok(())
+    }
+    #[test]
+    fn argument_not_specified() -> Result<(), Box<dyn std::error::Error>> {
+        let v = [ActionParameter::new("123")];
+        let mut par = ActionParametersSlice::new(&v[..]);
+        let x: i32 = par.try_parameters_into(&mut ())?;
+        assert_eq!(x, 123);
+        assert_eq!(par.index(), 1);
+        assert!(par.remaining().is_empty());
+        let x: Result<i32, Error> = par.try_parameters_into(&mut ());
+        match x {
+            Err(Error::ArgumentNotSpecified { index, action }) => {
+                assert_eq!(index, 1);
+                assert!(action.is_none());
+            }
+            x => panic!("ArgumentNotSpecified expected, got {:?}", x),
+        }
+        Ok(())
+    }
+    #[test]
+    fn parameter_kind_and_position() -> Result<(), Box<dyn std::error::Error>> {
+        let query = crate::parse::parse("abc-x-~X~def~E")?;
+        let parameters = &query.segments[0].query[0].parameters;
+        assert_eq!(parameters[0].kind(), ParameterKind::String);
+        assert_eq!(parameters[0].position().offset, 4);
+        assert_eq!(parameters[1].kind(), ParameterKind::Link);
+        assert_eq!(parameters[1].position().offset, 6);
+        assert_eq!(ActionParameter::new("x").position().offset, 0);
+        Ok(())
+    }
+    #[test]
+    fn rename_action() -> Result<(), Box<dyn std::error::Error>> {
+        let mut query = crate::parse::parse("old-1/x/-old/old-~X~a/old~E/-seg/y/old")?;
+        assert_eq!(query.rename_action("old", "new"), 5);
+        assert_eq!(query.encode(), "new-1/x/-new/new-~X~a/new~E/-seg/y/new");
+        assert_eq!(query.rename_action("old", "new"), 0);
+        Ok(())
+    }
+    #[test]
+    fn query_json_schema_properties() -> Result<(), Box<dyn std::error::Error>> {
+        let schema = query_json_schema();
+        assert!(schema["properties"]["segments"].is_object());
+        assert_eq!(schema["title"], "Query");
+        for name in [
+            "QuerySegment",
+            "SegmentHeader",
+            "ActionRequest",
+            "ActionParameter",
+            "Position",
+        ]
+        .iter()
+        {
+            assert!(schema["definitions"][name].is_object(), "{} missing", name);
+        }
+        // Properties of the serialized structures match the schema
+        let query = serde_json::to_value(crate::parse::parse("-seg-1/abc-x")?)?;
+        let definitions = &schema["definitions"];
+        let keys = |value: &serde_json::Value| {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        let segment = &query["segments"][0];
+        assert_eq!(keys(segment), keys(&definitions["QuerySegment"]["properties"]));
+        assert_eq!(keys(&segment["header"]), keys(&definitions["SegmentHeader"]["properties"]));
+        assert_eq!(keys(&segment["query"][0]), keys(&definitions["ActionRequest"]["properties"]));
+        assert_eq!(
+            keys(&segment["query"][0]["position"]),
+            keys(&definitions["Position"]["properties"])
+        );
+        Ok(())
+    }
+    #[test]
+    fn deserialize_without_end_position() -> Result<(), Box<dyn std::error::Error>> {
+        let json = r#"{"name":"add","position":{"offset":0,"line":1,"column":1},"parameters":[]}"#;
+        let request: ActionRequest = serde_json::from_str(json)?;
+        assert_eq!(request.name, "add");
+        assert_eq!(request.end_position.line, 0);
+        Ok(())
+    }
+    #[test]
+    fn deserialize_without_name_position() -> Result<(), Box<dyn std::error::Error>> {
+        let json = r#"{"name":"seg","level":1,"position":{"offset":0,"line":1,"column":1},"parameters":[]}"#;
+        let header: SegmentHeader = serde_json::from_str(json)?;
+        assert_eq!(header.name, "seg");
+        assert_eq!(header.name_position.line, 0);
+        Ok(())
+    }
+    #[test]
+    fn segment_with_without_header() -> Result<(), Box<dyn std::error::Error>> {
+        let segment = crate::parse::parse("abc-1/def")?.segments[0].clone();
+        assert!(segment.header.is_none());
+        let header = crate::parse::parse("-seg-x")?.segments[0]
+            .header
+            .clone()
+            .unwrap();
+        let segment = segment.with_header(header);
+        assert_eq!(segment.encode(), "-seg-x/abc-1/def");
+        let segment = segment.without_header();
+        assert_eq!(segment.encode(), "abc-1/def");
+        Ok(())
+    }
+    #[test]
+    fn segment_accessors() -> Result<(), Box<dyn std::error::Error>> {
+        let mut query = crate::parse::parse("abc/-x/def/-y")?;
+        assert_eq!(query.segment_count(), 3);
+        assert!(query.segment(0).unwrap().header.is_none());
+        assert_eq!(query.segment(1).unwrap().query[0].name, "def");
+        assert_eq!(query.segment(2).unwrap().header.as_ref().unwrap().name, "y");
+        assert!(query.segment(3).is_none());
+        assert!(query.segment_mut(3).is_none());
+        query.segment_mut(2).unwrap().query.push(
+            crate::parse::parse_action_request("ghi")?
+        );
+        assert_eq!(query.encode(), "abc/-x/def/-y/ghi");
+        assert_eq!(Query::new().segment_count(), 0);
+        Ok(())
+    }
 }
@@ -5,15 +5,458 @@ use std::result::Result;
 use crate::error::Error;
 use crate::formats::*;
 use std::convert::{TryFrom, TryInto};
+use std::collections::BTreeMap;
+use std::borrow::Cow;
+use strum::IntoEnumIterator;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub enum Value{
+    /// Missing value; corresponds to JSON null.
+    /// Inside an object None is an ordinary value - the key is kept (e.g. `{"a": null}`),
+    /// while an absent key is simply not present in the object.
+    #[default]
     None,
     Text(String),
     Integer(i32),
     Real(f64),
     Bool(bool),
     Bytes(Vec<u8>),
+    /// List of values.
+    /// This is as well the way how an action returns multiple outputs:
+    /// it returns a list and the next action selects the output it needs (e.g. with the item action).
+    List(Vec<Value>),
+    /// Object (dictionary) with string keys.
+    Object(BTreeMap<String, Value>),
+    /// Opaque handle to a host-specific value which can't be serialized.
+    /// Only the id is stored (and serialized); the value itself lives outside of the query pipeline.
+    ///
+    /// Resolution contract: the id is assigned by the host environment, which keeps the table
+    /// mapping ids to the actual payloads. Liquers core never interprets the id - it just passes it
+    /// through the pipeline. Only actions provided by the host resolve it;
+    /// all the generic conversions fail with a ConversionError.
+    External(String),
+    /// Date and time (UTC) as milliseconds since the unix epoch.
+    /// Serialized (e.g. in JSON) as an ISO-8601 string like `2021-03-04T05:06:07.089Z`.
+    DateTime(#[serde(with = "iso8601")] i64),
+}
+
+/// Rounding applied when converting a real number to an integer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RoundingMode{
+    /// Towards zero
+    Truncate,
+    /// To the nearest integer, half away from zero
+    Round,
+    /// Towards negative infinity
+    Floor,
+    /// Towards positive infinity
+    Ceil,
+}
+
+/// Non-consuming typed accessors.
+/// Unlike the TryFrom conversions, these only succeed for the matching variant
+/// (except as_real, which accepts an integer as well).
+impl Value{
+    pub fn as_integer(&self)->Option<i32>{
+        match self{
+            Value::Integer(x) => Some(*x),
+            _ => None
+        }
+    }
+    pub fn as_real(&self)->Option<f64>{
+        match self{
+            Value::Real(x) => Some(*x),
+            Value::Integer(x) => Some(*x as f64),
+            _ => None
+        }
+    }
+    pub fn as_text(&self)->Option<String>{
+        match self{
+            Value::Text(x) => Some(x.to_owned()),
+            _ => None
+        }
+    }
+    pub fn as_bool(&self)->Option<bool>{
+        match self{
+            Value::Bool(x) => Some(*x),
+            _ => None
+        }
+    }
+    /// Format (extension) preferred for serialization of the value:
+    /// raw bytes for bytes, txt for text and json for everything else.
+    pub fn preferred_format(&self)->&'static str{
+        match self{
+            Value::Bytes(_) => "b",
+            Value::Text(_) => "txt",
+            _ => "json"
+        }
+    }
+    /// Serialize value in the preferred format; returns the serialized bytes and the media type.
+    pub fn serialize_default(&self)->Result<(Vec<u8>, String), Error>{
+        let format = self.preferred_format();
+        Ok((self.as_bytes(format)?, media_type_from_extension(format).to_owned()))
+    }
+    /// True if the value is or contains a real number which is NaN or infinite.
+    pub fn has_non_finite_real(&self)->bool{
+        match self{
+            Value::Real(x) => !x.is_finite(),
+            Value::List(x) => x.iter().any(|v| v.has_non_finite_real()),
+            Value::Object(x) => x.values().any(|v| v.has_non_finite_real()),
+            _ => false
+        }
+    }
+    /// Borrowed text of a Value::Text; unlike as_text it doesn't clone the string.
+    pub fn as_str(&self)->Option<&str>{
+        match self{
+            Value::Text(x) => Some(x),
+            _ => None
+        }
+    }
+    /// Text representation for read-only access: borrows the text of Value::Text (and of valid UTF-8 bytes),
+    /// other values are formatted - numbers, bools and datetimes as in `TryFrom<Value> for String`, None as an empty string,
+    /// invalid UTF-8 bytes lossily, lists, objects and external values as JSON (format `json`).
+    pub fn to_cow_str(&self)->Cow<'_, str>{
+        match self{
+            Value::None => Cow::Borrowed(""),
+            Value::Text(x) => Cow::Borrowed(x),
+            Value::Integer(x) => Cow::Owned(format!("{}",x)),
+            Value::Real(x) => Cow::Owned(format!("{}",x)),
+            Value::Bool(x) => Cow::Owned(format!("{}",x)),
+            Value::Bytes(x) => String::from_utf8_lossy(x),
+            Value::DateTime(x) => Cow::Owned(format_iso8601(*x)),
+            _ => Cow::Owned(serde_json::to_string(self).unwrap_or_default()),
+        }
+    }
+    /// Bytes accessor; named as_byte_slice since as_bytes is used by ValueSerializer.
+    pub fn as_byte_slice(&self)->Option<&[u8]>{
+        match self{
+            Value::Bytes(x) => Some(&x[..]),
+            _ => None
+        }
+    }
+    /// Convert a number to Value::Integer, rounding real numbers with the given mode.
+    /// Fails with a ConversionError for non-numeric values and for reals out of the integer range (or NaN).
+    pub fn to_integer_with(&self, mode:RoundingMode)->Result<Value, Error>{
+        match self{
+            Value::Integer(x) => Ok(Value::Integer(*x)),
+            Value::Real(x) => {
+                let rounded = match mode{
+                    RoundingMode::Truncate => x.trunc(),
+                    RoundingMode::Round => x.round(),
+                    RoundingMode::Floor => x.floor(),
+                    RoundingMode::Ceil => x.ceil(),
+                };
+                if rounded.is_nan() || rounded < i32::MIN as f64 || rounded > i32::MAX as f64{
+                    Err(Error::ConversionError{message:format!("Can't convert real number {} to integer; out of range", x)})
+                }
+                else{
+                    Ok(Value::Integer(rounded as i32))
+                }
+            },
+            _ => Err(Error::ConversionError{message:format!("Can't convert {} to integer", self.type_identifier())})
+        }
+    }
+    /// Equality with a tolerance for numbers: integers and reals are equal if they differ by at most epsilon
+    /// (an integer may be compared with a real); lists and objects are compared element-wise,
+    /// other values exactly.
+    pub fn approx_eq(&self, other:&Value, epsilon:f64)->bool{
+        match (self, other){
+            (Value::List(a), Value::List(b)) => a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.approx_eq(y, epsilon)),
+            (Value::Object(a), Value::Object(b)) => a.len() == b.len()
+                && a.iter().all(|(key, x)| b.get(key).is_some_and(|y| x.approx_eq(y, epsilon))),
+            (Value::Integer(_), _) | (Value::Real(_), _) => match (self.as_real(), other.as_real()){
+                (Some(x), Some(y)) => (x - y).abs() <= epsilon,
+                _ => false
+            },
+            _ => self == other
+        }
+    }
+    /// Truthiness of the value, used by conditional actions.
+    /// Whenever the value can be converted to bool (see `TryFrom<Value> for bool`), the result is the same:
+    /// None, false, 0, 0.0 and the text "false" (case-insensitive) are false, "true" is true.
+    /// For the other values, empty text, empty bytes, empty list and empty object are false,
+    /// anything else (including an external value and a datetime) is true.
+    pub fn is_truthy(&self)->bool{
+        match self{
+            Value::None => false,
+            Value::Text(x) => !(x.is_empty() || x.to_lowercase() == "false"),
+            Value::Integer(x) => *x != 0,
+            Value::Real(x) => *x != 0.0,
+            Value::Bool(x) => *x,
+            Value::Bytes(x) => !x.is_empty(),
+            Value::List(x) => !x.is_empty(),
+            Value::Object(x) => !x.is_empty(),
+            Value::External(_) => true,
+            Value::DateTime(_) => true,
+        }
+    }
+    /// Merge two values: objects are merged deeply (on conflict the other's value wins,
+    /// unless both values are objects, which are merged recursively), lists are concatenated.
+    /// Other combinations are not mergeable and result in an error.
+    pub fn merge(&self, other:&Value)->Result<Value, Error>{
+        match (self, other){
+            (Value::Object(a), Value::Object(b)) => {
+                let mut merged = a.clone();
+                for (key, value) in b.iter(){
+                    let value = match (merged.get(key), value){
+                        (Some(x@Value::Object(_)), Value::Object(_)) => x.merge(value)?,
+                        _ => value.clone()
+                    };
+                    merged.insert(key.to_owned(), value);
+                }
+                Ok(Value::Object(merged))
+            },
+            (Value::List(a), Value::List(b)) => Ok(Value::List(a.iter().chain(b.iter()).cloned().collect())),
+            _ => Err(Error::General{message:format!("Can't merge {} with {}", self.type_identifier(), other.type_identifier())})
+        }
+    }
+}
+
+/// Date and time.
+impl Value{
+    /// Datetime from an ISO-8601 string (see parse_iso8601).
+    pub fn datetime_from_iso(text:&str)->Result<Value, Error>{
+        parse_iso8601(text).map(Value::DateTime)
+    }
+    /// Convert to Value::DateTime: a datetime is kept, a text is parsed as ISO-8601.
+    pub fn to_datetime(&self)->Result<Value, Error>{
+        match self{
+            Value::DateTime(x) => Ok(Value::DateTime(*x)),
+            Value::Text(x) => Value::datetime_from_iso(x),
+            _ => Err(Error::ConversionError{message:format!("Can't convert {} to datetime", self.type_identifier())})
+        }
+    }
+    /// Milliseconds since the unix epoch of a Value::DateTime.
+    pub fn as_datetime(&self)->Option<i64>{
+        match self{
+            Value::DateTime(x) => Some(*x),
+            _ => None
+        }
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year:i64, month:i64, day:i64)->i64{
+    let year = if month <= 2 {year - 1} else {year};
+    let era = year.div_euclid(400);
+    let year_of_era = year - era*400;
+    let day_of_year = (153*((month + 9) % 12) + 2)/5 + day - 1;
+    let day_of_era = year_of_era*365 + year_of_era/4 - year_of_era/100 + day_of_year;
+    era*146097 + day_of_era - 719468
+}
+
+/// Date (year, month, day) of the given number of days since 1970-01-01; inverse of days_from_civil.
+fn civil_from_days(days:i64)->(i64, i64, i64){
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era*146097;
+    let year_of_era = (day_of_era - day_of_era/1460 + day_of_era/36524 - day_of_era/146096)/365;
+    let day_of_year = day_of_era - (365*year_of_era + year_of_era/4 - year_of_era/100);
+    let mp = (5*day_of_year + 2)/153;
+    let day = day_of_year - (153*mp + 2)/5 + 1;
+    let month = if mp < 10 {mp + 3} else {mp - 9};
+    let year = year_of_era + era*400 + if month <= 2 {1} else {0};
+    (year, month, day)
+}
+
+/// Format milliseconds since the unix epoch as an ISO-8601 string in UTC, e.g. `2021-03-04T05:06:07.089Z`.
+pub fn format_iso8601(millis:i64)->String{
+    let days = millis.div_euclid(86_400_000);
+    let ms = millis.rem_euclid(86_400_000);
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, ms/3_600_000, (ms/60_000)%60, (ms/1000)%60, ms%1000)
+}
+
+/// Parse an ISO-8601 date (`2021-03-04`) or datetime (`2021-03-04T05:06:07`) into milliseconds since the unix epoch.
+/// The time may have a fraction of a second (digits beyond milliseconds are truncated)
+/// and a timezone - `Z` or an offset like `+01:00`; without a timezone the time is taken as UTC.
+pub fn parse_iso8601(text:&str)->Result<i64, Error>{
+    let error = || Error::ConversionError{message:format!("Invalid ISO-8601 datetime {}", text)};
+    let number = |s:&str, min:i64, max:i64|{
+        if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()){
+            return Err(error());
+        }
+        s.parse::<i64>().ok().filter(|x| *x >= min && *x <= max).ok_or_else(error)
+    };
+    let (date, time) = match text.find('T'){
+        Some(i) => (&text[..i], Some(&text[i+1..])),
+        None => (text, None)
+    };
+    let date_parts:Vec<&str> = date.split('-').collect();
+    if date_parts.len() != 3 || date_parts[0].len() != 4 || date_parts[1].len() != 2 || date_parts[2].len() != 2{
+        return Err(error());
+    }
+    let (year, month) = (number(date_parts[0], 0, 9999)?, number(date_parts[1], 1, 12)?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month{
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    };
+    let day = number(date_parts[2], 1, days_in_month)?;
+    let mut millis = days_from_civil(year, month, day)*86_400_000;
+    if let Some(time) = time{
+        let (time, offset) = if let Some(time) = time.strip_suffix('Z'){
+            (time, 0)
+        }
+        else if let Some(i) = time.rfind(['+', '-']){
+            let (hours, minutes) = time[i+1..].split_once(':').ok_or_else(error)?;
+            let offset = (number(hours, 0, 23)?*60 + number(minutes, 0, 59)?)*60_000;
+            (&time[..i], if time[i..].starts_with('-') {-offset} else {offset})
+        }
+        else{
+            (time, 0)
+        };
+        let (time, fraction) = match time.split_once('.'){
+            Some((time, fraction)) => (time, Some(fraction)),
+            None => (time, None)
+        };
+        let time_parts:Vec<&str> = time.split(':').collect();
+        if time_parts.len() != 3 || time_parts.iter().any(|x| x.len() != 2){
+            return Err(error());
+        }
+        millis += number(time_parts[0], 0, 23)?*3_600_000 + number(time_parts[1], 0, 59)?*60_000 + number(time_parts[2], 0, 59)?*1000;
+        if let Some(fraction) = fraction{
+            if fraction.is_empty() || !fraction.chars().all(|c| c.is_ascii_digit()){
+                return Err(error());
+            }
+            let digits:String = fraction.chars().chain("000".chars()).take(3).collect();
+            millis += number(&digits, 0, 999)?;
+        }
+        millis -= offset;
+    }
+    Ok(millis)
+}
+
+/// Serde (de)serialization of Value::DateTime as an ISO-8601 string.
+mod iso8601{
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S:Serializer>(millis:&i64, serializer:S)->Result<S::Ok, S::Error>{
+        serializer.serialize_str(&super::format_iso8601(*millis))
+    }
+
+    pub fn deserialize<'de, D:Deserializer<'de>>(deserializer:D)->Result<i64, D::Error>{
+        let text = String::deserialize(deserializer)?;
+        super::parse_iso8601(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// CSV (or TSV) serialization.
+impl Value{
+    fn csv_cell(&self, delimiter:char)->Result<String, Error>{
+        let text = match self{
+            Value::None => String::new(),
+            Value::Text(x) => x.to_owned(),
+            Value::Integer(x) => format!("{}",x),
+            Value::Real(x) => format!("{}",x),
+            Value::Bool(x) => format!("{}",x),
+            Value::DateTime(x) => format_iso8601(*x),
+            _ => return Err(Error::SerializationError{message:format!("Can't serialize {} as a CSV cell", self.type_identifier()), format:"csv".to_owned()})
+        };
+        if text.contains(delimiter) || text.contains('"') || text.contains('\n') || text.contains('\r'){
+            Ok(format!("\"{}\"", text.replace('"', "\"\"")))
+        }
+        else{
+            Ok(text)
+        }
+    }
+
+    /// Serialize a list as CSV with the given delimiter.
+    /// List items are rows; a row is a list of cells, an object (columns are the keys of the first object) or a single cell.
+    pub fn as_csv(&self, delimiter:char)->Result<String, Error>{
+        let rows = match self{
+            Value::List(rows) => rows,
+            _ => return Err(Error::SerializationError{message:format!("Can't serialize {} as CSV, list expected", self.type_identifier()), format:"csv".to_owned()})
+        };
+        let separator = delimiter.to_string();
+        let mut lines = Vec::with_capacity(rows.len()+1);
+        let header:Option<Vec<String>> = match rows.first(){
+            Some(Value::Object(first)) => Some(first.keys().cloned().collect()),
+            _ => None
+        };
+        if let Some(header) = &header{
+            let cells = header.iter().map(|x| Value::from(x.as_str()).csv_cell(delimiter)).collect::<Result<Vec<_>,_>>()?;
+            lines.push(cells.join(&separator));
+        }
+        for row in rows.iter(){
+            let cells = match (row, &header){
+                (Value::List(cells), _) => cells.iter().map(|x| x.csv_cell(delimiter)).collect::<Result<Vec<_>,_>>()?,
+                (Value::Object(object), Some(header)) => header.iter()
+                    .map(|key| object.get(key).unwrap_or(&Value::None).csv_cell(delimiter))
+                    .collect::<Result<Vec<_>,_>>()?,
+                (x, _) => vec![x.csv_cell(delimiter)?]
+            };
+            lines.push(cells.join(&separator));
+        }
+        Ok(lines.into_iter().map(|x| x+"\n").collect())
+    }
+
+    /// Parse CSV with the given delimiter into a list of rows; each row is a list of texts.
+    pub fn from_csv(text:&str, delimiter:char)->Value{
+        let mut rows = vec![];
+        let mut row = vec![];
+        let mut cell = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next(){
+            match c{
+                '"' if quoted && chars.peek()==Some(&'"') => {
+                    chars.next();
+                    cell.push('"');
+                },
+                '"' => quoted = !quoted,
+                c if quoted => cell.push(c),
+                c if c == delimiter => row.push(Value::Text(std::mem::take(&mut cell))),
+                '\r' => {},
+                '\n' => {
+                    row.push(Value::Text(std::mem::take(&mut cell)));
+                    rows.push(Value::List(std::mem::take(&mut row)));
+                },
+                c => cell.push(c)
+            }
+        }
+        if !cell.is_empty() || !row.is_empty(){
+            row.push(Value::Text(cell));
+            rows.push(Value::List(row));
+        }
+        Value::List(rows)
+    }
+}
+
+/// Replace integral real numbers (serialized as `{"Real": 4.0}`) by integers (`{"Real": 4}`).
+/// Only reals exactly representable as integers (below 2^53 in absolute value) are replaced.
+fn compact_integral_reals(json:&mut serde_json::Value){
+    match json{
+        serde_json::Value::Object(map) => {
+            if map.len() == 1{
+                if let Some(serde_json::Value::Number(x)) = map.get_mut("Real"){
+                    if let Some(f) = x.as_f64().filter(|f| f.fract() == 0.0 && f.abs() < 9_007_199_254_740_992.0){
+                        *x = serde_json::Number::from(f as i64);
+                    }
+                    return;
+                }
+            }
+            map.values_mut().for_each(compact_integral_reals);
+        },
+        serde_json::Value::Array(items) => items.iter_mut().for_each(compact_integral_reals),
+        _ => {}
+    }
+}
+
+/// Writer counting the bytes written (see serialized_len).
+struct ByteCounter(usize);
+
+impl std::io::Write for ByteCounter{
+    fn write(&mut self, buf:&[u8])->std::io::Result<usize>{
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+    fn flush(&mut self)->std::io::Result<()>{
+        Ok(())
+    }
 }
 
 impl ValueSerializer for Value{
@@ -26,6 +469,10 @@ impl ValueSerializer for Value{
             Value::Real(_) => String::from("real"),
             Value::Bool(_) => String::from("bool"),
             Value::Bytes(_) => String::from("bytes"),
+            Value::List(_) => String::from("list"),
+            Value::Object(_) => String::from("object"),
+            Value::External(_) => String::from("external"),
+            Value::DateTime(_) => String::from("datetime"),
         }
     }
     fn default_extension(&self)->String{
@@ -34,20 +481,124 @@ impl ValueSerializer for Value{
     fn default_media_type(&self)->String{
         String::from("application/json")
     }
+    /// Depends on the variant: binary formats are only supported for Bytes,
+    /// csv and tsv only for List and txt not for List, Object and External.
+    fn supported_formats(&self)->Vec<String>{
+        ValueSerializationFormats::iter()
+            .filter(|x| match x{
+                ValueSerializationFormats::Binary => matches!(self, Value::Bytes(_)),
+                ValueSerializationFormats::Csv | ValueSerializationFormats::Tsv => matches!(self, Value::List(_)),
+                ValueSerializationFormats::Text => !matches!(self, Value::List(_) | Value::Object(_) | Value::External(_)),
+                _ => true
+            })
+            .map(|x| x.default_extension().to_owned())
+            .collect()
+    }
     fn as_bytes(&self, format:&str)->Result<Vec<u8>, Error>{
+        self.as_bytes_with(&SerializeOptions::new(format))
+    }
+    /// Exact for all formats; JSON is written into a byte counter instead of a buffer.
+    fn serialized_len(&self, format:&str)->Result<usize, Error>{
+        match format{
+            "json" | "serde.json" if !self.has_non_finite_real() => {
+                let mut counter = ByteCounter(0);
+                serde_json::to_writer(&mut counter, self)
+                .map_err(|e| Error::SerializationError{message:format!("JSON errror {}",e), format:format.to_owned()})?;
+                Ok(counter.0)
+            },
+            _ => Ok(self.as_bytes(format)?.len())
+        }
+    }
+    fn as_bytes_with(&self, options:&SerializeOptions)->Result<Vec<u8>, Error>{
+        let format = &options.format[..];
         match format{
-            "json" => serde_json::to_vec(self).map_err(|e| Error::SerializationError{message:format!("JSON errror {}",e), format:format.to_owned()}),
+            "json" | "serde.json" if options.non_finite_reals == NonFiniteRealPolicy::Error && self.has_non_finite_real() =>
+                Err(Error::SerializationError{message:"NaN or infinite real number can't be serialized as JSON".to_owned(), format:format.to_owned()}),
+            "json" | "serde.json" if options.integral_reals_as_int => {
+                let mut json = serde_json::to_value(self).map_err(|e| Error::SerializationError{message:format!("JSON errror {}",e), format:format.to_owned()})?;
+                compact_integral_reals(&mut json);
+                if options.pretty {serde_json::to_vec_pretty(&json)} else {serde_json::to_vec(&json)}
+                .map_err(|e| Error::SerializationError{message:format!("JSON errror {}",e), format:format.to_owned()})
+            },
+            "json" | "serde.json" if options.pretty => serde_json::to_vec_pretty(self).map_err(|e| Error::SerializationError{message:format!("JSON errror {}",e), format:format.to_owned()}),
+            "json" | "serde.json" => serde_json::to_vec(self).map_err(|e| Error::SerializationError{message:format!("JSON errror {}",e), format:format.to_owned()}),
+            // Identity binary codec
+            "b" | "bytes" | "raw" => match self{
+                Value::Bytes(x) => Ok(x.to_owned()),
+                _ => Err(Error::SerializationError{message:format!("Binary format not supported for {}",self.type_identifier()), format:format.to_owned()}),
+            },
+            "csv" => self.as_csv(options.delimiter.unwrap_or(',')).map(|x| x.into_bytes()),
+            "tsv" => self.as_csv(options.delimiter.unwrap_or('\t')).map(|x| x.into_bytes()),
+            "txt" => match self{
+                Value::None => Ok(vec![]),
+                Value::Bytes(x) => Ok(x.to_owned()),
+                _ => String::try_from(self.clone())
+                    .map(|x| x.into_bytes())
+                    .map_err(|e| Error::SerializationError{message:format!("Text serialization error {}",e), format:format.to_owned()}),
+            },
             _ => Err(Error::SerializationError{message:format!("Unsupported format {}",format), format:format.to_owned()})
         }
     }
     fn from_bytes(b: &[u8], format:&str)->Result<Self, Error>{
         match format{
-            "json" => serde_json::from_slice(b).map_err(|e| Error::SerializationError{message:format!("JSON errror {}",e), format:format.to_owned()}),
+            "b" | "bytes" | "raw" => Ok(Value::Bytes(b.to_vec())),
+            "csv" | "tsv" => {
+                let text = std::str::from_utf8(b)
+                    .map_err(|e| Error::SerializationError{message:format!("Text deserialization error {}",e), format:format.to_owned()})?;
+                Ok(Value::from_csv(text, if format=="tsv" {'\t'} else {','}))
+            },
+            "txt" => String::from_utf8(b.to_vec()).map(Value::Text)
+                .map_err(|e| Error::SerializationError{message:format!("Text deserialization error {}",e), format:format.to_owned()}),
+            "json" | "serde.json" => serde_json::from_slice(b).map_err(|e| Error::SerializationError{message:format!("JSON errror {}",e), format:format.to_owned()}),
             _ => Err(Error::SerializationError{message:format!("Unsupported format {}",format), format:format.to_owned()})
         }
     }
 }
 
+/// Bridge from plain JSON (as opposed to the tagged serde serialization of Value).
+/// Null becomes None (keeping the key in objects), integers fitting into i32 become Integer,
+/// other numbers become Real.
+impl From<serde_json::Value> for Value{
+    fn from(value: serde_json::Value) -> Value{
+        match value{
+            serde_json::Value::Null => Value::None,
+            serde_json::Value::Bool(x) => Value::Bool(x),
+            serde_json::Value::Number(x) => x.as_i64()
+                .and_then(|x| i32::try_from(x).ok())
+                .map(Value::Integer)
+                .unwrap_or_else(|| Value::Real(x.as_f64().unwrap_or(f64::NAN))),
+            serde_json::Value::String(x) => Value::Text(x),
+            serde_json::Value::Array(x) => Value::List(x.into_iter().map(Value::from).collect()),
+            serde_json::Value::Object(x) => Value::Object(x.into_iter().map(|(k, v)| (k, Value::from(v))).collect()),
+        }
+    }
+}
+
+/// Bridge to plain JSON; None becomes null (keeping the key in objects).
+/// Bytes, external values and non-finite real numbers have no plain JSON representation.
+impl TryFrom<Value> for serde_json::Value{
+    type Error=Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error>{
+        match value{
+            Value::None => Ok(serde_json::Value::Null),
+            Value::Text(x) => Ok(serde_json::Value::String(x)),
+            Value::Integer(x) => Ok(serde_json::Value::from(x)),
+            Value::Real(x) => serde_json::Number::from_f64(x)
+                .map(serde_json::Value::Number)
+                .ok_or_else(|| Error::ConversionError{message:format!("Can't convert {} to JSON", x)}),
+            Value::Bool(x) => Ok(serde_json::Value::Bool(x)),
+            Value::Bytes(_) => Err(Error::ConversionError{message:"Can't convert bytes to JSON".to_owned()}),
+            Value::List(x) => x.into_iter().map(serde_json::Value::try_from).collect::<Result<Vec<_>,_>>().map(serde_json::Value::Array),
+            Value::Object(x) => x.into_iter()
+                .map(|(k, v)| serde_json::Value::try_from(v).map(|v| (k, v)))
+                .collect::<Result<serde_json::Map<_,_>,_>>()
+                .map(serde_json::Value::Object),
+            Value::External(_) => Err(Error::ConversionError{message:"Can't convert external value to JSON".to_owned()}),
+            Value::DateTime(x) => Ok(serde_json::Value::String(format_iso8601(x))),
+        }
+    }
+}
+
 impl TryFrom<Value> for i32{
     type Error=Error;
     fn try_from(value: Value) -> Result<Self, Self::Error>{
@@ -58,6 +609,10 @@ impl TryFrom<Value> for i32{
             Value::Integer(x) => Ok(x),
             Value::Real(_) => Err(Error::ConversionError{message:format!("Can't convert real number to integer")}),
             Value::Bytes(_) => Err(Error::ConversionError{message:format!("Can't convert bytes to integer")}),
+            Value::List(_) => Err(Error::ConversionError{message:"Can't convert list to integer".to_owned()}),
+            Value::Object(_) => Err(Error::ConversionError{message:"Can't convert object to integer".to_owned()}),
+            Value::External(_) => Err(Error::ConversionError{message:"Can't convert external value to integer".to_owned()}),
+            Value::DateTime(_) => Err(Error::ConversionError{message:"Can't convert datetime to integer".to_owned()}),
         }
     }
 }
@@ -78,6 +633,10 @@ impl TryFrom<Value> for f64{
             Value::Integer(x) => Ok(x as f64),
             Value::Real(x) => Ok(x),
             Value::Bytes(_) => Err(Error::ConversionError{message:format!("Can't convert bytes to real number")}),
+            Value::List(_) => Err(Error::ConversionError{message:"Can't convert list to real number".to_owned()}),
+            Value::Object(_) => Err(Error::ConversionError{message:"Can't convert object to real number".to_owned()}),
+            Value::External(_) => Err(Error::ConversionError{message:"Can't convert external value to real number".to_owned()}),
+            Value::DateTime(_) => Err(Error::ConversionError{message:"Can't convert datetime to real number".to_owned()}),
         }
     }
 }
@@ -104,6 +663,10 @@ impl TryFrom<Value> for bool{
             Value::Integer(x) => Ok(x!=0),
             Value::Real(x) => Ok(x!=0.0),
             Value::Bytes(_) => Err(Error::ConversionError{message:format!("Can't convert bytes to bool")}),
+            Value::List(_) => Err(Error::ConversionError{message:"Can't convert list to bool".to_owned()}),
+            Value::Object(_) => Err(Error::ConversionError{message:"Can't convert object to bool".to_owned()}),
+            Value::External(_) => Err(Error::ConversionError{message:"Can't convert external value to bool".to_owned()}),
+            Value::DateTime(_) => Err(Error::ConversionError{message:"Can't convert datetime to bool".to_owned()}),
         }
     }
 }
@@ -125,7 +688,11 @@ impl TryFrom<Value> for String{
             Value::Bool(x) => Ok(format!("{}",x)),
             Value::Bytes(x) => {
                 String::from_utf8(x).map_err(|e| Error::ConversionError{message:format!("Conversion of bytes to string failed; {}",e)})
-            }
+            },
+            Value::List(_) => Err(Error::ConversionError{message:"Can't convert list to string".to_owned()}),
+            Value::Object(_) => Err(Error::ConversionError{message:"Can't convert object to string".to_owned()}),
+            Value::External(_) => Err(Error::ConversionError{message:"Can't convert external value to string".to_owned()}),
+            Value::DateTime(x) => Ok(format_iso8601(x)),
         }
     }
 }
@@ -141,6 +708,33 @@ impl From<&str> for Value{
     }
 }
 
+impl From<&[u8]> for Value{
+    fn from(value: &[u8]) -> Value{
+        Value::Bytes(value.to_vec())
+    }
+}
+impl From<Vec<u8>> for Value{
+    fn from(value: Vec<u8>) -> Value{
+        Value::Bytes(value)
+    }
+}
+
+impl TryFrom<Value> for Vec<Value>{
+    type Error=Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error>{
+        match value{
+            Value::List(x) => Ok(x),
+            _ => Err(Error::ConversionError{message:format!("Can't convert {} to list", value.type_identifier())}),
+        }
+    }
+}
+
+impl From<Vec<Value>> for Value{
+    fn from(value: Vec<Value>) -> Value{
+        Value::List(value)
+    }
+}
+
 #[cfg(test)]
 mod tests{
     use super::*;
@@ -186,4 +780,334 @@ mod tests{
         assert_eq!(v,Value::Bool(false));
         Ok(())
     }   
+    #[test]
+    fn test_external_json_roundtrip() -> Result<(), Box<dyn std::error::Error>>{
+        let v = Value::External("handle-123".to_owned());
+        assert_eq!(v.type_identifier(), "external");
+        let b = v.as_bytes("json")?;
+        let w:Value = ValueSerializer::from_bytes(&b, "json")?;
+        assert_eq!(w, Value::External("handle-123".to_owned()));
+        let x:Result<i32,Error> = w.try_into();
+        assert!(x.is_err());
+        Ok(())
+    }   
+    #[test]
+    fn test_accessors() -> Result<(), Box<dyn std::error::Error>>{
+        assert_eq!(Value::Integer(123).as_integer(), Some(123));
+        assert_eq!(Value::Text("123".to_owned()).as_integer(), None);
+        assert_eq!(Value::Real(1.5).as_real(), Some(1.5));
+        assert_eq!(Value::Integer(2).as_real(), Some(2.0));
+        assert_eq!(Value::Bool(true).as_real(), None);
+        assert_eq!(Value::from("abc").as_text(), Some("abc".to_owned()));
+        assert_eq!(Value::Integer(1).as_text(), None);
+        assert_eq!(Value::Bool(true).as_bool(), Some(true));
+        assert_eq!(Value::Integer(1).as_bool(), None);
+        assert_eq!(Value::Bytes(vec![1,2]).as_byte_slice(), Some(&[1u8,2u8][..]));
+        assert_eq!(Value::None.as_byte_slice(), None);
+        Ok(())
+    }   
+    #[test]
+    fn test_convert_list() -> Result<(), Box<dyn std::error::Error>>{
+        let v = Value::from(vec![Value::Integer(1), Value::from("a")]);
+        assert_eq!(v.type_identifier(), "list");
+        let x:Vec<Value> = v.try_into()?;
+        assert_eq!(x.len(), 2);
+        let x:Result<Vec<Value>,Error> = Value::Integer(1).try_into();
+        assert!(x.is_err());
+        Ok(())
+    }   
+    #[test]
+    fn test_supported_formats() -> Result<(), Box<dyn std::error::Error>>{
+        let v = Value::Integer(123);
+        let formats = v.supported_formats();
+        assert!(formats.contains(&"json".to_owned()));
+        for format in formats.iter(){
+            let b = v.as_bytes(format)?;
+            Value::from_bytes(&b, format)?;
+        }
+        assert_eq!(v.as_bytes("txt")?, b"123".to_vec());
+        assert_eq!(Value::from_bytes(b"abc", "txt")?, Value::from("abc"));
+        for v in [Value::Bytes(b"abc".to_vec()), Value::List(vec![Value::Integer(1)])].iter(){
+            for format in v.supported_formats().iter(){
+                v.as_bytes(format)?;
+            }
+        }
+        assert!(Value::Bytes(vec![]).supported_formats().contains(&"b".to_owned()));
+        assert!(Value::List(vec![]).supported_formats().contains(&"csv".to_owned()));
+        Ok(())
+    }   
+    #[test]
+    fn test_non_finite_json() -> Result<(), Box<dyn std::error::Error>>{
+        for x in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY].iter(){
+            let v = Value::Real(*x);
+            assert!(v.as_bytes("json").is_err());
+            let mut options = SerializeOptions::new("json");
+            options.non_finite_reals = NonFiniteRealPolicy::Null;
+            assert_eq!(std::str::from_utf8(&v.as_bytes_with(&options)?)?, r#"{"Real":null}"#);
+        }
+        let v = Value::List(vec![Value::Real(1.0), Value::Real(f64::NAN)]);
+        assert!(v.as_bytes("json").is_err());
+        assert!(Value::Real(f64::NAN).as_bytes("txt").is_ok());
+        Ok(())
+    }   
+    #[test]
+    fn test_preferred_format() -> Result<(), Box<dyn std::error::Error>>{
+        let v = Value::Bytes(vec![0,1,255]);
+        assert_eq!(v.preferred_format(), "b");
+        assert_eq!(v.serialize_default()?, (vec![0,1,255], "application/octet-stream".to_owned()));
+        let v = Value::from("abc");
+        assert_eq!(v.preferred_format(), "txt");
+        assert_eq!(v.serialize_default()?, (b"abc".to_vec(), "text/plain".to_owned()));
+        let mut object = BTreeMap::new();
+        object.insert("a".to_owned(), Value::Integer(1));
+        let v = Value::Object(object);
+        assert_eq!(v.type_identifier(), "object");
+        assert_eq!(v.preferred_format(), "json");
+        let (b, media_type) = v.serialize_default()?;
+        assert_eq!(media_type, "application/json");
+        assert_eq!(Value::from_bytes(&b, "json")?, v);
+        Ok(())
+    }   
+    #[test]
+    fn test_csv() -> Result<(), Box<dyn std::error::Error>>{
+        let v = Value::List(vec![
+            Value::List(vec![Value::from("a"), Value::Integer(1)]),
+            Value::List(vec![Value::from("b,c"), Value::Real(2.5)]),
+        ]);
+        assert_eq!(std::str::from_utf8(&v.as_bytes("csv")?)?, "a,1\n\"b,c\",2.5\n");
+        assert_eq!(std::str::from_utf8(&v.as_bytes("tsv")?)?, "a\t1\nb,c\t2.5\n");
+        let w = Value::from_bytes(&v.as_bytes("csv")?, "csv")?;
+        assert_eq!(w, Value::List(vec![
+            Value::List(vec![Value::from("a"), Value::from("1")]),
+            Value::List(vec![Value::from("b,c"), Value::from("2.5")]),
+        ]));
+        let mut object = BTreeMap::new();
+        object.insert("x".to_owned(), Value::Integer(1));
+        object.insert("y".to_owned(), Value::from("q\""));
+        let v = Value::List(vec![Value::Object(object)]);
+        assert_eq!(v.as_csv(',')?, "x,y\n1,\"q\"\"\"\n");
+        assert!(Value::Integer(1).as_bytes("csv").is_err());
+        Ok(())
+    }   
+    #[test]
+    fn test_merge() -> Result<(), Error>{
+        let object = |items:&[(&str, Value)]| Value::Object(items.iter().map(|(k,v)| (k.to_string(), v.clone())).collect());
+        let a = object(&[("x", Value::Integer(1)), ("y", Value::Integer(2)), ("z", object(&[("p", Value::Integer(1)), ("q", Value::Integer(2))]))]);
+        let b = object(&[("y", Value::from("b")), ("w", Value::Bool(true)), ("z", object(&[("q", Value::Integer(3))]))]);
+        assert_eq!(a.merge(&b)?, object(&[
+            ("x", Value::Integer(1)),
+            ("y", Value::from("b")),
+            ("w", Value::Bool(true)),
+            ("z", object(&[("p", Value::Integer(1)), ("q", Value::Integer(3))])),
+        ]));
+        let a = Value::List(vec![Value::Integer(1)]);
+        let b = Value::List(vec![Value::Integer(2), Value::Integer(3)]);
+        assert_eq!(a.merge(&b)?, Value::List(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]));
+        assert!(a.merge(&Value::Integer(1)).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_from_bytes_limited(){
+        let nested = format!("{}{}", "{\"List\":[".repeat(100), "]}".repeat(100));
+        match Value::from_bytes_limited(nested.as_bytes(), "json", 10000, 32){
+            Err(Error::LimitExceeded{..}) => {},
+            x => panic!("LimitExceeded expected, got {:?}", x)
+        }
+        match Value::from_bytes_limited(&[0;100], "b", 10, 32){
+            Err(Error::LimitExceeded{..}) => {},
+            x => panic!("LimitExceeded expected, got {:?}", x)
+        }
+        assert_eq!(Value::from_bytes_limited(br#"{"List":[{"Integer":1}]}"#, "json", 100, 3).unwrap(), Value::List(vec![Value::Integer(1)]));
+    }
+    #[test]
+    fn test_raw_format() -> Result<(), Error>{
+        let payload = Value::Bytes(vec![0, 1, 2, 255, 254, 10, 13]);
+        for format in ["b", "bytes", "raw"].iter(){
+            let bytes = payload.as_bytes(format)?;
+            assert_eq!(bytes, vec![0, 1, 2, 255, 254, 10, 13]);
+            assert_eq!(Value::from_bytes(&bytes, format)?, payload);
+        }
+        assert!(Value::from("abc").as_bytes("raw").is_err());
+        assert_eq!(ValueSerializationFormats::from_filename("data.raw"), Some(ValueSerializationFormats::Binary));
+        assert_eq!(ValueSerializationFormats::from_filename("raw"), Some(ValueSerializationFormats::Binary));
+        // Extension must follow a dot
+        assert_eq!(ValueSerializationFormats::from_filename("x.ab"), None);
+        assert_eq!(ValueSerializationFormats::from_filename("page.web"), None);
+        assert_eq!(ValueSerializationFormats::from_filename("draw"), None);
+        Ok(())
+    }
+    #[test]
+    fn test_serialize_options() -> Result<(), Box<dyn std::error::Error>>{
+        let v = Value::List(vec![Value::List(vec![Value::from("a;b"), Value::Integer(1)])]);
+        let options = SerializeOptions{delimiter:Some(';'), ..SerializeOptions::new("csv")};
+        assert_eq!(std::str::from_utf8(&v.as_bytes_with(&options)?)?, "\"a;b\";1\n");
+        let v = Value::List(vec![Value::Integer(1)]);
+        let options = SerializeOptions{pretty:true, ..SerializeOptions::new("json")};
+        assert_eq!(std::str::from_utf8(&v.as_bytes_with(&options)?)?, "{\n  \"List\": [\n    {\n      \"Integer\": 1\n    }\n  ]\n}");
+        assert_eq!(v.as_bytes("json")?, br#"{"List":[{"Integer":1}]}"#.to_vec());
+        Ok(())
+    }
+    #[test]
+    fn test_integral_reals_as_int() -> Result<(), Box<dyn std::error::Error>>{
+        let v = Value::Real(4.0);
+        assert_eq!(v.as_bytes("json")?, br#"{"Real":4.0}"#.to_vec());
+        let options = SerializeOptions{integral_reals_as_int:true, ..SerializeOptions::new("json")};
+        let b = v.as_bytes_with(&options)?;
+        assert_eq!(b, br#"{"Real":4}"#.to_vec());
+        assert_eq!(Value::from_bytes(&b, "json")?, v);
+        let v = Value::List(vec![Value::Real(4.5), Value::Real(-2.0), Value::Integer(1)]);
+        assert_eq!(v.as_bytes_with(&options)?, br#"{"List":[{"Real":4.5},{"Real":-2},{"Integer":1}]}"#.to_vec());
+        Ok(())
+    }
+    #[test]
+    fn test_is_truthy(){
+        let table = vec![
+            (Value::None, false),
+            (Value::from(""), false),
+            (Value::from("false"), false),
+            (Value::from("FALSE"), false),
+            (Value::from("true"), true),
+            (Value::from("abc"), true),
+            (Value::Integer(0), false),
+            (Value::Integer(-1), true),
+            (Value::Real(0.0), false),
+            (Value::Real(0.5), true),
+            (Value::Bool(false), false),
+            (Value::Bool(true), true),
+            (Value::Bytes(vec![]), false),
+            (Value::Bytes(vec![0]), true),
+            (Value::List(vec![]), false),
+            (Value::List(vec![Value::None]), true),
+            (Value::Object(BTreeMap::new()), false),
+            (Value::Object(vec![("a".to_owned(), Value::None)].into_iter().collect()), true),
+            (Value::External("x".to_owned()), true),
+        ];
+        for (value, expected) in table.into_iter(){
+            assert_eq!(value.is_truthy(), expected, "{:?}", value);
+            if let Ok(b) = bool::try_from(value.clone()){
+                assert_eq!(b, expected, "{:?}", value);
+            }
+        }
+    }
+    #[test]
+    fn test_json_null_roundtrip() -> Result<(), Box<dyn std::error::Error>>{
+        let json:serde_json::Value = serde_json::from_str(r#"{"a":null,"b":[1,2.5,null],"c":"x"}"#)?;
+        let value = Value::from(json.clone());
+        match &value{
+            Value::Object(object) => {
+                assert_eq!(object.get("a"), Some(&Value::None));
+                assert_eq!(object.get("d"), None);
+            },
+            x => panic!("Object expected, got {:?}", x)
+        }
+        assert_eq!(serde_json::Value::try_from(value.clone())?, json);
+        assert_eq!(Value::from_bytes(&value.as_bytes("json")?, "json")?, value);
+        assert!(serde_json::Value::try_from(Value::Bytes(vec![1])).is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_to_integer_with() -> Result<(), Error>{
+        let x = Value::Real(2.7);
+        assert_eq!(x.to_integer_with(RoundingMode::Truncate)?, Value::Integer(2));
+        assert_eq!(x.to_integer_with(RoundingMode::Round)?, Value::Integer(3));
+        assert_eq!(x.to_integer_with(RoundingMode::Floor)?, Value::Integer(2));
+        assert_eq!(x.to_integer_with(RoundingMode::Ceil)?, Value::Integer(3));
+        let x = Value::Real(-2.7);
+        assert_eq!(x.to_integer_with(RoundingMode::Truncate)?, Value::Integer(-2));
+        assert_eq!(x.to_integer_with(RoundingMode::Floor)?, Value::Integer(-3));
+        assert_eq!(Value::Integer(5).to_integer_with(RoundingMode::Floor)?, Value::Integer(5));
+        assert!(Value::Real(1e10).to_integer_with(RoundingMode::Round).is_err());
+        assert!(Value::Real(f64::NAN).to_integer_with(RoundingMode::Round).is_err());
+        assert!(Value::from("2").to_integer_with(RoundingMode::Round).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_bytes_slice() -> Result<(), Box<dyn std::error::Error>>{
+        let data:&[u8] = &[0, 1, 255];
+        let v = Value::from(data);
+        assert_eq!(v, Value::Bytes(vec![0, 1, 255]));
+        assert_eq!(v.as_byte_slice(), Some(data));
+        assert_eq!(Value::from(vec![7u8, 8]).as_byte_slice(), Some(&[7u8, 8][..]));
+        assert_eq!(Value::from("text").as_byte_slice(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialized_len() -> Result<(), Box<dyn std::error::Error>>{
+        let values = [
+            Value::None,
+            Value::Integer(-123),
+            Value::Real(1.5),
+            Value::from("Příliš \"quoted\""),
+            Value::from(vec![Value::Integer(1), Value::from(vec![Value::Bool(true)])]),
+            Value::Object(vec![("a".to_owned(), Value::Integer(1))].into_iter().collect()),
+        ];
+        for v in values.iter(){
+            assert_eq!(v.serialized_len("json")?, v.as_bytes("json")?.len());
+            assert_eq!(v.serialized_len("txt").ok(), v.as_bytes("txt").ok().map(|x| x.len()));
+        }
+        assert!(Value::Real(f64::NAN).serialized_len("json").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_str() -> Result<(), Box<dyn std::error::Error>>{
+        let v = Value::from("hello");
+        let text = v.as_str().unwrap();
+        assert_eq!(text, "hello");
+        match &v{
+            Value::Text(x) => assert!(std::ptr::eq(text.as_ptr(), x.as_ptr())),
+            _ => unreachable!(),
+        }
+        assert_eq!(Value::Integer(1).as_str(), None);
+        assert!(matches!(v.to_cow_str(), Cow::Borrowed("hello")));
+        assert!(matches!(Value::from(&b"abc"[..]).to_cow_str(), Cow::Borrowed("abc")));
+        assert_eq!(Value::Integer(12).to_cow_str(), "12");
+        assert_eq!(Value::Bool(true).to_cow_str(), "true");
+        assert_eq!(Value::None.to_cow_str(), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_datetime() -> Result<(), Box<dyn std::error::Error>>{
+        let v = Value::datetime_from_iso("2021-03-04T05:06:07.089Z")?;
+        assert_eq!(v, Value::DateTime(1614834367089));
+        assert_eq!(v.type_identifier(), "datetime");
+        let b = v.as_bytes("json")?;
+        assert_eq!(std::str::from_utf8(&b)?, r#"{"DateTime":"2021-03-04T05:06:07.089Z"}"#);
+        assert_eq!(Value::from_bytes(&b, "json")?, v);
+        assert_eq!(String::try_from(v.clone())?, "2021-03-04T05:06:07.089Z");
+        assert_eq!(serde_json::Value::try_from(v.clone())?, serde_json::json!("2021-03-04T05:06:07.089Z"));
+
+        assert_eq!(Value::from("2021-03-04T06:06:07.089+01:00").to_datetime()?, v);
+        assert_eq!(Value::from("2021-03-04T05:06:07.0891").to_datetime()?, v);
+        assert_eq!(Value::from("1970-01-01").to_datetime()?, Value::DateTime(0));
+        assert_eq!(Value::from("1969-12-31T23:59:59.999Z").to_datetime()?, Value::DateTime(-1));
+        assert_eq!(format_iso8601(-1), "1969-12-31T23:59:59.999Z");
+        assert_eq!(format_iso8601(951782400000), "2000-02-29T00:00:00.000Z");
+        for text in ["2021-3-04", "2021-03-04T05:06", "2021-13-01", "2021-02-31", "2021-02-29", "1900-02-29", "2021-04-31", "2021-03-04T05:06:07.Z", "x"].iter(){
+            assert!(Value::from(*text).to_datetime().is_err(), "{}", text);
+        }
+        assert!(Value::Integer(1).to_datetime().is_err());
+        assert!(Value::from("2020-02-29").to_datetime().is_ok());
+        assert!(Value::from("2000-02-29").to_datetime().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_approx_eq() -> Result<(), Box<dyn std::error::Error>>{
+        assert!(Value::Real(1.0000001).approx_eq(&Value::Real(1.0), 1e-5));
+        assert!(!Value::Real(1.0000001).approx_eq(&Value::Real(1.0), 1e-9));
+        assert!(Value::Integer(1).approx_eq(&Value::Real(1.0000001), 1e-5));
+        let a = Value::List(vec![Value::Real(0.1+0.2), Value::from("x")]);
+        let b = Value::List(vec![Value::Real(0.3), Value::from("x")]);
+        assert!(a.approx_eq(&b, 1e-9));
+        assert!(!a.approx_eq(&Value::List(vec![Value::Real(0.3)]), 1e-9));
+        let object = |x:f64| Value::Object(vec![("a".to_owned(), Value::Real(x))].into_iter().collect());
+        assert!(object(2.0).approx_eq(&object(2.000001), 1e-5));
+        assert!(!object(2.0).approx_eq(&object(2.1), 1e-5));
+        assert!(!Value::from("1").approx_eq(&Value::Integer(1), 1e-5));
+        Ok(())
+    }
 }
\ No newline at end of file